@@ -1,22 +1,235 @@
-use clap::Parser;
+use crate::errdef;
+use clap::{Parser, Subcommand};
+use squidc::emit::Stage;
+use std::path::PathBuf;
 
 /// Argument Parser
 #[derive(Parser, Debug)]
-#[command(author, about, long_about = None)]
+#[command(author, about, long_about = None, after_long_help = errdef::EXIT_CODES_HELP)]
 pub struct Args {
-    /// Bundle VM executable with compiled bytecode *make executable*
-    #[arg(long, short)]
-    pub bundle: bool,
-
-    /// Number of optimization steps
-    #[arg(long, short)]
-    pub optimizations: Option<u8>,
+    #[command(subcommand)]
+    pub command: Option<Command>,
 
     /// Shows the SquidC version | SquidC |major|.|minor|.|patch|-|details| for |OS| |arch|
-    #[arg(long, short = 'V')]
+    #[arg(long, short = 'V', global = true)]
     pub version: bool,
 
     /// Shows newer versions if detected
-    #[arg(long, visible_alias = "cnv")]
+    #[arg(long, visible_alias = "cnv", global = true)]
     pub check_updates: bool,
+
+    /// Explains a compiler error code, like SQ0005
+    #[arg(long, value_name = "CODE", global = true)]
+    pub explain: Option<String>,
+
+    /// Benchmarks the parallel front end against a single thread
+    #[cfg(feature = "devkit")]
+    #[arg(long)]
+    pub bench_frontend: bool,
+
+    /// Measures the lexer throughput on a few megabytes of code
+    #[cfg(feature = "devkit")]
+    #[arg(long)]
+    pub bench_lexer: bool,
+}
+
+#[derive(Subcommand, Debug)]
+pub enum Command {
+    /// Compiles the project described by the nearest Squid.toml, or a single
+    /// entry file and its imports
+    Build {
+        /// Entry file to compile instead of a project, imports are resolved
+        /// from its directory
+        #[arg(conflicts_with_all = ["manifest_path", "no_cache", "watch"])]
+        input: Option<PathBuf>,
+
+        /// Manifest to build, defaults to the nearest Squid.toml
+        #[arg(long)]
+        manifest_path: Option<PathBuf>,
+
+        /// Number of optimization steps, overrides the manifest
+        #[arg(long, short = 'O')]
+        optimizations: Option<u8>,
+
+        /// Also bundles the VM executable with the compiled bytecode
+        #[arg(long, short)]
+        bundle: bool,
+
+        /// SquidVM executable to bundle with, overrides the manifest
+        #[arg(long)]
+        vm: Option<PathBuf>,
+
+        /// Where to write the bytecode, or the executable when bundling,
+        /// defaults to the name of the entry file
+        #[arg(long, short, requires = "input")]
+        output: Option<PathBuf>,
+
+        /// Compiles every module again, ignoring the build cache
+        #[arg(long)]
+        no_cache: bool,
+
+        /// Keeps running and rebuilds whenever a source file or manifest changes
+        #[arg(long, short)]
+        watch: bool,
+
+        /// Writes the given compiler stages next to the output, as
+        /// <output>.<stage>.txt
+        #[arg(long, value_enum, value_delimiter = ',')]
+        emit: Vec<Stage>,
+
+        /// Prints the stages given to --emit instead of writing them to files
+        #[arg(long, requires = "emit")]
+        emit_stdout: bool,
+    },
+
+    /// Runs the front end on a project or an entry file and reports errors,
+    /// without writing anything
+    Check {
+        /// Entry file to check instead of a project
+        #[arg(conflicts_with = "manifest_path")]
+        input: Option<PathBuf>,
+
+        /// Manifest to check, defaults to the nearest Squid.toml
+        #[arg(long)]
+        manifest_path: Option<PathBuf>,
+    },
+
+    /// Builds the nearest project and runs it with the VM set in its manifest
+    Run {
+        /// Manifest to run, defaults to the nearest Squid.toml
+        #[arg(long)]
+        manifest_path: Option<PathBuf>,
+
+        /// Number of optimization steps, overrides the manifest
+        #[arg(long, short = 'O')]
+        optimizations: Option<u8>,
+
+        /// SquidVM executable to run with, overrides the manifest
+        #[arg(long)]
+        vm: Option<PathBuf>,
+
+        /// Compiles every module again, ignoring the build cache
+        #[arg(long)]
+        no_cache: bool,
+
+        /// Arguments passed to the program
+        #[arg(last = true)]
+        args: Vec<String>,
+    },
+
+    /// Prints the tokens of a source file
+    Tokens {
+        /// Source file to tokenize
+        file: PathBuf,
+
+        /// Also prints the whitespace and comments before each token
+        #[arg(long)]
+        trivia: bool,
+    },
+
+    /// Prints the syntax tree of a source file
+    Ast {
+        /// Source file to parse
+        file: PathBuf,
+    },
+
+    /// Lists the modules in compiled bytecode or a bundled executable
+    Disasm {
+        /// Bytecode (.sqb) or bundled executable
+        file: PathBuf,
+    },
+
+    /// Formats source files in place
+    Fmt {
+        /// Files to format, defaults to the sources of the nearest project
+        files: Vec<PathBuf>,
+
+        /// Only reports files that aren't formatted, without changing them
+        #[arg(long)]
+        check: bool,
+    },
+
+    /// Writes Markdown documentation of the public items of a project or an
+    /// entry file and its imports
+    Doc {
+        /// Entry file to document instead of a project
+        #[arg(conflicts_with = "manifest_path")]
+        input: Option<PathBuf>,
+
+        /// Manifest of the project, defaults to the nearest Squid.toml
+        #[arg(long)]
+        manifest_path: Option<PathBuf>,
+
+        /// Where to write the documentation, defaults to the standard output
+        #[arg(long, short)]
+        output: Option<PathBuf>,
+    },
+
+    /// Runs the tests of the nearest project
+    Test {
+        /// Manifest of the project, defaults to the nearest Squid.toml
+        #[arg(long)]
+        manifest_path: Option<PathBuf>,
+    },
+
+    /// Starts an interactive session
+    Repl,
+
+    /// Manages this SquidC installation
+    #[command(name = "self")]
+    Installation {
+        #[command(subcommand)]
+        action: SelfAction,
+    },
+
+    /// Looks for a newer SquidC release, same as `self update`
+    Update,
+
+    /// Verifies a bundled executable and extracts its bytecode
+    Extract {
+        /// Bundled executable to read
+        bundle: PathBuf,
+
+        /// Where to write the bytecode, it's only verified when not given
+        #[arg(long, short)]
+        output: Option<PathBuf>,
+    },
+
+    /// Manages the build cache of the nearest project
+    Cache {
+        #[command(subcommand)]
+        action: CacheAction,
+
+        /// Manifest of the project, defaults to the nearest Squid.toml
+        #[arg(long, global = true)]
+        manifest_path: Option<PathBuf>,
+    },
+
+    /// Builds a .sqpkg package archive from an entry file and its imports
+    Package {
+        /// Entry file of the package
+        input: PathBuf,
+
+        /// Where to write the package, defaults to the name of the entry file
+        #[arg(long, short)]
+        output: Option<PathBuf>,
+    },
+
+    /// Lists the contents of a .sqpkg package archive and validates them
+    InspectPackage {
+        /// Package archive to inspect
+        package: PathBuf,
+    },
+}
+
+#[derive(Subcommand, Debug)]
+pub enum SelfAction {
+    /// Looks for a newer SquidC release
+    Update,
+}
+
+#[derive(Subcommand, Debug)]
+pub enum CacheAction {
+    /// Deletes the build cache, the next build compiles everything
+    Clean,
 }
@@ -0,0 +1,108 @@
+use crate::compiler_internals::modules::ModuleGraph;
+use crate::package;
+use byteorder::{ByteOrder, LittleEndian, WriteBytesExt};
+use std::fs;
+use std::io::Write;
+use std::path::Path;
+
+/// Marks the end of a bundled executable.
+const MAGIC: &[u8; 8] = b"SQUIDBDL";
+
+/// Payload offset (u64), payload length (u64), CRC-32 of the payload (u32)
+/// and the magic, in this order at the very end of the file.
+const TRAILER_LEN: usize = 8 + 8 + 4 + MAGIC.len();
+
+/// Serializes the linked modules into the payload of a bundle, as the
+/// [`package::module_image`] of each one in link order.
+pub fn image(graph: &ModuleGraph) -> Vec<u8> {
+    graph
+        .modules
+        .iter()
+        .flat_map(package::module_image)
+        .collect()
+}
+
+/// Writes a copy of the `vm` executable to `output` with `payload` and its
+/// trailer appended, and makes it executable.
+pub fn bundle(vm: &Path, payload: &[u8], output: &Path) -> Result<(), String> {
+    let mut executable =
+        fs::read(vm).map_err(|err| format!("can't read the VM at '{}': {err}", vm.display()))?;
+
+    if find_payload(&executable).is_ok() {
+        return Err(format!("'{}' is already a bundle", vm.display()));
+    }
+
+    let offset = executable.len() as u64;
+
+    executable.extend_from_slice(payload);
+    executable.write_u64::<LittleEndian>(offset).unwrap();
+    executable
+        .write_u64::<LittleEndian>(payload.len() as u64)
+        .unwrap();
+    executable
+        .write_u32::<LittleEndian>(crc32fast::hash(payload))
+        .unwrap();
+    executable.write_all(MAGIC).unwrap();
+
+    fs::write(output, executable)
+        .map_err(|err| format!("can't write '{}': {err}", output.display()))?;
+
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+
+        let mut permissions = fs::metadata(output)
+            .map_err(|err| format!("can't read '{}': {err}", output.display()))?
+            .permissions();
+        permissions.set_mode(permissions.mode() | 0o111);
+
+        fs::set_permissions(output, permissions)
+            .map_err(|err| format!("can't make '{}' executable: {err}", output.display()))?;
+    }
+
+    Ok(())
+}
+
+/// Reads the payload of a bundled executable, checking its checksum.
+pub fn extract(executable: &Path) -> Result<Vec<u8>, String> {
+    let bytes = fs::read(executable)
+        .map_err(|err| format!("can't read '{}': {err}", executable.display()))?;
+
+    find_payload(&bytes)
+        .map(<[u8]>::to_vec)
+        .map_err(|message| format!("'{}' {message}", executable.display()))
+}
+
+/// Checks if `bytes` end with the bundle magic, even if the rest of the
+/// bundle is corrupted.
+pub fn is_bundle(bytes: &[u8]) -> bool {
+    bytes.len() >= TRAILER_LEN && bytes.ends_with(MAGIC)
+}
+
+/// Payload of the bundled executable in `bytes`, checking its checksum.
+pub fn find_payload(bytes: &[u8]) -> Result<&[u8], String> {
+    if !is_bundle(bytes) {
+        return Err(String::from("isn't a bundled executable"));
+    }
+
+    let trailer = &bytes[bytes.len() - TRAILER_LEN..];
+    let offset = LittleEndian::read_u64(&trailer[0..8]);
+    let length = LittleEndian::read_u64(&trailer[8..16]);
+    let checksum = LittleEndian::read_u32(&trailer[16..20]);
+
+    // The payload always runs right up to the trailer.
+    let end = bytes.len() - TRAILER_LEN;
+    if offset > end as u64 || end as u64 - offset != length {
+        return Err(String::from("has a corrupted bundle trailer"));
+    }
+
+    let payload = &bytes[offset as usize..end];
+
+    if crc32fast::hash(payload) != checksum {
+        return Err(String::from(
+            "has a corrupted payload, its checksum doesn't match",
+        ));
+    }
+
+    Ok(payload)
+}
@@ -0,0 +1,392 @@
+use crate::codes::ErrorCode;
+use crate::compiler_internals::frontend::SourceFile;
+use crate::compiler_internals::modules::{Import, Module, ModuleGraph, Summary, Warning};
+use crate::compiler_internals::symbol::Symbol;
+use async_std::task::block_on;
+use sqlx::sqlite::{SqliteConnectOptions, SqliteConnection};
+use sqlx::{ConnectOptions, Connection, Executor, Row};
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// File name of the build cache, in the target directory.
+pub const CACHE_NAME: &str = "cache.sqlite";
+
+/// Version of what the cache stores: the tables, the summary JSON and the
+/// artifacts. Bump it when one of them changes, entries written in another
+/// format are then ignored even by the same compiler version.
+const CACHE_FORMAT: u32 = 2;
+
+// Older formats kept the imports of each module in a `dependencies` table,
+// the artifact keys already cover them.
+const SCHEMA: &str = "
+    DROP TABLE IF EXISTS dependencies;
+    CREATE TABLE IF NOT EXISTS modules (
+        path TEXT PRIMARY KEY,
+        compiler TEXT NOT NULL,
+        source_hash TEXT NOT NULL,
+        summary TEXT NOT NULL,
+        artifact_key TEXT NOT NULL,
+        artifact BLOB NOT NULL
+    );
+";
+
+/// What the cache knows about a module from the last build.
+struct Entry {
+    source_hash: u64,
+    summary: String,
+    artifact_key: u64,
+    artifact: Vec<u8>,
+}
+
+/// Persistent build cache, an SQLite database in the target directory.
+///
+/// It keeps the [`Summary`] of each module, keyed by the hash of its source,
+/// and its compiled artifact, keyed by [`artifact_key`]. Entries written by
+/// another compiler version or in another [`CACHE_FORMAT`] are ignored.
+pub struct Cache {
+    connection: SqliteConnection,
+    entries: HashMap<PathBuf, Entry>,
+}
+
+impl Cache {
+    /// Opens the cache at `path`, creating it if needed, and reads it whole.
+    pub fn open(path: &Path) -> Result<Self, String> {
+        let err =
+            |err: sqlx::Error| format!("can't open the build cache '{}': {err}", path.display());
+
+        block_on(async {
+            let mut connection = SqliteConnectOptions::new()
+                .filename(path)
+                .create_if_missing(true)
+                .connect()
+                .await
+                .map_err(err)?;
+
+            connection.execute(SCHEMA).await.map_err(err)?;
+
+            let rows = sqlx::query(
+                "SELECT path, source_hash, summary, artifact_key, artifact
+                 FROM modules WHERE compiler = ?",
+            )
+            .bind(compiler())
+            .fetch_all(&mut connection)
+            .await
+            .map_err(err)?;
+
+            let entries = rows
+                .iter()
+                .filter_map(|row| {
+                    let entry = Entry {
+                        source_hash: u64::from_str_radix(row.get("source_hash"), 16).ok()?,
+                        summary: row.get("summary"),
+                        artifact_key: u64::from_str_radix(row.get("artifact_key"), 16).ok()?,
+                        artifact: row.get("artifact"),
+                    };
+
+                    Some((PathBuf::from(row.get::<String, _>("path")), entry))
+                })
+                .collect();
+
+            Ok(Self {
+                connection,
+                entries,
+            })
+        })
+    }
+
+    /// Summary of `source` from the last build, if it hasn't changed since.
+    pub fn summary(&self, source: &SourceFile) -> Option<Summary> {
+        let entry = self.entries.get(&source.path)?;
+
+        if entry.source_hash != hash(&[source.code.as_bytes()]) {
+            return None;
+        }
+
+        summary_from_json(&serde_json::from_str(&entry.summary).ok()?)
+    }
+
+    /// Artifact of the module at `path`, if it was compiled with the same key.
+    pub fn artifact(&self, path: &Path, key: u64) -> Option<&[u8]> {
+        let entry = self.entries.get(path)?;
+        (entry.artifact_key == key).then_some(entry.artifact.as_slice())
+    }
+
+    /// Replaces the cache contents with the modules of `graph` and their
+    /// artifacts.
+    pub fn save(
+        &mut self,
+        graph: &ModuleGraph,
+        keys: &[u64],
+        artifacts: &[Vec<u8>],
+    ) -> Result<(), String> {
+        let err = |err: sqlx::Error| format!("can't write the build cache: {err}");
+
+        block_on(async {
+            let mut transaction = self.connection.begin().await.map_err(err)?;
+
+            transaction
+                .execute("DELETE FROM modules")
+                .await
+                .map_err(err)?;
+
+            for ((module, key), artifact) in graph.modules.iter().zip(keys).zip(artifacts) {
+                let path = module.source.path.to_string_lossy();
+
+                sqlx::query(
+                    "INSERT INTO modules
+                     (path, compiler, source_hash, summary, artifact_key, artifact)
+                     VALUES (?, ?, ?, ?, ?, ?)",
+                )
+                .bind(path.as_ref())
+                .bind(compiler())
+                .bind(format!("{:016x}", hash(&[module.source.code.as_bytes()])))
+                .bind(summary_to_json(&module.summary()).to_string())
+                .bind(format!("{key:016x}"))
+                .bind(artifact.as_slice())
+                .execute(&mut *transaction)
+                .await
+                .map_err(err)?;
+            }
+
+            transaction.commit().await.map_err(err)
+        })
+    }
+}
+
+/// Key of the compiled artifact of `module`: a hash of the compiler version
+/// and [`CACHE_FORMAT`], the build `flags`, its source and the keys of the
+/// modules it imports.
+///
+/// A change anywhere in its transitive imports changes the key, so the module
+/// gets recompiled.
+pub fn artifact_key(module: &Module, flags: &str, dependencies: impl Iterator<Item = u64>) -> u64 {
+    let source_hash = hash(&[module.source.code.as_bytes()]).to_le_bytes();
+    let dependencies: Vec<u8> = dependencies.flat_map(u64::to_le_bytes).collect();
+
+    hash(&[
+        compiler().as_bytes(),
+        flags.as_bytes(),
+        &source_hash,
+        &dependencies,
+    ])
+}
+
+/// Compiler version and cache format the entries were written with.
+fn compiler() -> String {
+    format!("{} format {CACHE_FORMAT}", env!("CARGO_PKG_VERSION"))
+}
+
+/// Removes the build cache from `target`, returning whether there was one.
+pub fn clean(target: &Path) -> Result<bool, String> {
+    let mut removed = false;
+
+    // SQLite keeps its journal next to the database.
+    for suffix in ["", "-journal", "-wal", "-shm"] {
+        let path = target.join(format!("{CACHE_NAME}{suffix}"));
+
+        if path.exists() {
+            fs::remove_file(&path)
+                .map_err(|err| format!("can't remove '{}': {err}", path.display()))?;
+            removed = true;
+        }
+    }
+
+    Ok(removed)
+}
+
+/// 64-bit FNV-1a of `parts`, each one prefixed by its length so they can't
+/// run into each other.
+fn hash(parts: &[&[u8]]) -> u64 {
+    let mut hash: u64 = 0xcbf2_9ce4_8422_2325;
+
+    for part in parts {
+        for byte in (part.len() as u64).to_le_bytes().iter().chain(*part) {
+            hash ^= *byte as u64;
+            hash = hash.wrapping_mul(0x0000_0100_0000_01b3);
+        }
+    }
+
+    hash
+}
+
+fn summary_to_json(summary: &Summary) -> serde_json::Value {
+    fn names(symbols: &HashSet<Symbol>) -> Vec<&str> {
+        let mut names: Vec<&str> = symbols.iter().map(Symbol::as_str).collect();
+        names.sort();
+        names
+    }
+
+    serde_json::json!({
+        "imports": summary.imports.iter().map(|import| serde_json::json!({
+            "path": import.path.iter().map(|symbol| symbol.as_str()).collect::<Vec<_>>(),
+            "std": import.std,
+            "position": import.position,
+        })).collect::<Vec<_>>(),
+        "exports": names(&summary.exports),
+        "items": names(&summary.items),
+        "warnings": summary.warnings.iter().map(|warning| serde_json::json!({
+            "code": warning.code.to_string(),
+            "message": warning.message,
+            "position": warning.position,
+        })).collect::<Vec<_>>(),
+    })
+}
+
+fn summary_from_json(json: &serde_json::Value) -> Option<Summary> {
+    let symbols = |value: &serde_json::Value| -> Option<Vec<Symbol>> {
+        value
+            .as_array()?
+            .iter()
+            .map(|name| name.as_str().map(Symbol::intern))
+            .collect()
+    };
+
+    let imports = json["imports"]
+        .as_array()?
+        .iter()
+        .map(|import| {
+            Some(Import {
+                path: symbols(&import["path"])?,
+                std: import["std"].as_bool()?,
+                position: import["position"].as_u64()? as usize,
+                implicit: false,
+            })
+        })
+        .collect::<Option<Vec<_>>>()?;
+
+    let warnings = json["warnings"]
+        .as_array()?
+        .iter()
+        .map(|warning| {
+            Some(Warning {
+                code: ErrorCode::parse(warning["code"].as_str()?)?,
+                message: warning["message"].as_str()?.to_string(),
+                position: warning["position"].as_u64()? as usize,
+            })
+        })
+        .collect::<Option<Vec<_>>>()?;
+
+    Some(Summary {
+        imports,
+        exports: symbols(&json["exports"])?.into_iter().collect(),
+        items: symbols(&json["items"])?.into_iter().collect(),
+        warnings,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::compiler_internals::modules::{load_project, ModuleFile, Package};
+
+    /// Loads `main.sq` from a package kept in memory at `/app`.
+    fn load(files: &[(&str, &str)]) -> ModuleGraph {
+        let package = Package {
+            sources: vec![PathBuf::from("/app")],
+            dependencies: HashMap::new(),
+            files: files
+                .iter()
+                .map(|(path, code)| (Path::new("/app").join(path), code.to_string()))
+                .collect(),
+        };
+        let entry = ModuleFile {
+            name: String::from("main"),
+            path: PathBuf::from("/app/main.sq"),
+            package: Some(0),
+        };
+
+        load_project(&[package], vec![entry], |_| None).expect("the modules loaded")
+    }
+
+    /// Artifact keys of the modules of `graph`, computed like a build does.
+    fn keys(graph: &ModuleGraph, flags: &str) -> Vec<u64> {
+        let mut keys = Vec::new();
+
+        for module in &graph.modules {
+            let key = artifact_key(
+                module,
+                flags,
+                module
+                    .dependencies
+                    .iter()
+                    .map(|dependency| keys[*dependency]),
+            );
+            keys.push(key);
+        }
+
+        keys
+    }
+
+    fn main_key(files: &[(&str, &str)], flags: &str) -> u64 {
+        *keys(&load(files), flags).last().unwrap()
+    }
+
+    #[test]
+    fn artifact_keys_change_with_the_source_flags_and_imports() {
+        let files = [
+            ("main.sq", "import app::a;\nfn main() {}"),
+            ("app/a.sq", "pub fn one() {}"),
+        ];
+        let key = main_key(&files, "optimizations=0");
+
+        assert_eq!(key, main_key(&files, "optimizations=0"));
+        assert_ne!(key, main_key(&files, "optimizations=1"));
+        assert_ne!(
+            key,
+            main_key(
+                &[("main.sq", "import app::a;\nfn main() { }"), files[1]],
+                "optimizations=0"
+            )
+        );
+        assert_ne!(
+            key,
+            main_key(
+                &[files[0], ("app/a.sq", "pub fn one() { }")],
+                "optimizations=0"
+            )
+        );
+    }
+
+    #[test]
+    fn entries_are_invalidated() {
+        let target = std::env::temp_dir().join(format!("squidc-cache-{}", std::process::id()));
+        fs::create_dir_all(&target).unwrap();
+        let path = target.join(CACHE_NAME);
+
+        let graph = load(&[("main.sq", "fn main() {}")]);
+        let keys = keys(&graph, "");
+        let main = &graph.modules.last().unwrap().source;
+        let key = *keys.last().unwrap();
+
+        let mut cache = Cache::open(&path).unwrap();
+        let artifacts: Vec<Vec<u8>> = graph.modules.iter().map(|_| vec![1, 2, 3]).collect();
+        cache.save(&graph, &keys, &artifacts).unwrap();
+
+        let mut cache = Cache::open(&path).unwrap();
+        let edited = SourceFile {
+            path: main.path.clone(),
+            code: String::from("fn main() { }"),
+        };
+        let summary = cache.summary(main);
+        let unchanged = cache.artifact(&main.path, key).map(<[u8]>::to_vec);
+        let changed_summary = cache.summary(&edited);
+        let changed_artifact = cache.artifact(&main.path, key + 1).is_some();
+
+        // Entries written in another format, or by another compiler version.
+        block_on(
+            cache
+                .connection
+                .execute("UPDATE modules SET compiler = 'old'"),
+        )
+        .unwrap();
+        let old = Cache::open(&path).unwrap().summary(main);
+        fs::remove_dir_all(&target).unwrap();
+
+        assert!(summary.is_some());
+        assert_eq!(unchanged, Some(vec![1, 2, 3]));
+        assert!(changed_summary.is_none());
+        assert!(!changed_artifact);
+        assert!(old.is_none());
+    }
+}
@@ -0,0 +1,89 @@
+use std::fmt;
+
+/// Number of a compiler error or warning, shown as `SQ0001`.
+///
+/// Every error and warning found in source code has one, and
+/// `squidc --explain SQ0001` prints the long explanation of it, with examples.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct ErrorCode(u16);
+
+impl ErrorCode {
+    pub const UNREADABLE_FILE: ErrorCode = ErrorCode(1);
+    pub const BIDI_CONTROL: ErrorCode = ErrorCode(2);
+    pub const UNEXPECTED_CHARACTER: ErrorCode = ErrorCode(3);
+    pub const RESERVED_WORD: ErrorCode = ErrorCode(4);
+    pub const UNTERMINATED_STRING: ErrorCode = ErrorCode(5);
+    pub const INVALID_ESCAPE: ErrorCode = ErrorCode(6);
+    pub const INVALID_NUMBER: ErrorCode = ErrorCode(7);
+    pub const NUMBER_OUT_OF_RANGE: ErrorCode = ErrorCode(8);
+    pub const MALFORMED_IMPORT: ErrorCode = ErrorCode(9);
+    pub const MODULE_NOT_FOUND: ErrorCode = ErrorCode(10);
+    pub const ITEM_NOT_FOUND: ErrorCode = ErrorCode(11);
+    pub const PRIVATE_ITEM: ErrorCode = ErrorCode(12);
+    pub const CYCLIC_IMPORT: ErrorCode = ErrorCode(13);
+    pub const CONFUSABLE_IDENTIFIERS: ErrorCode = ErrorCode(14);
+    pub const SUSPICIOUS_IDENTIFIER: ErrorCode = ErrorCode(15);
+
+    /// Every code, in order.
+    pub const ALL: [ErrorCode; 15] = [
+        Self::UNREADABLE_FILE,
+        Self::BIDI_CONTROL,
+        Self::UNEXPECTED_CHARACTER,
+        Self::RESERVED_WORD,
+        Self::UNTERMINATED_STRING,
+        Self::INVALID_ESCAPE,
+        Self::INVALID_NUMBER,
+        Self::NUMBER_OUT_OF_RANGE,
+        Self::MALFORMED_IMPORT,
+        Self::MODULE_NOT_FOUND,
+        Self::ITEM_NOT_FOUND,
+        Self::PRIVATE_ITEM,
+        Self::CYCLIC_IMPORT,
+        Self::CONFUSABLE_IDENTIFIERS,
+        Self::SUSPICIOUS_IDENTIFIER,
+    ];
+
+    /// Finds a code written like `SQ0005`, `sq0005` or `5`.
+    pub fn parse(code: &str) -> Option<ErrorCode> {
+        let digits = match code.get(..2) {
+            Some(prefix) if prefix.eq_ignore_ascii_case("sq") => &code[2..],
+            _ => code,
+        };
+
+        if digits.is_empty() || !digits.bytes().all(|byte| byte.is_ascii_digit()) {
+            return None;
+        }
+
+        let number: u16 = digits.parse().ok()?;
+        Self::ALL.into_iter().find(|code| code.0 == number)
+    }
+
+    /// Long explanation of the error, with an example of code causing it and
+    /// how to fix it.
+    pub fn explanation(self) -> &'static str {
+        match self {
+            Self::UNREADABLE_FILE => include_str!("codes/SQ0001.md"),
+            Self::BIDI_CONTROL => include_str!("codes/SQ0002.md"),
+            Self::UNEXPECTED_CHARACTER => include_str!("codes/SQ0003.md"),
+            Self::RESERVED_WORD => include_str!("codes/SQ0004.md"),
+            Self::UNTERMINATED_STRING => include_str!("codes/SQ0005.md"),
+            Self::INVALID_ESCAPE => include_str!("codes/SQ0006.md"),
+            Self::INVALID_NUMBER => include_str!("codes/SQ0007.md"),
+            Self::NUMBER_OUT_OF_RANGE => include_str!("codes/SQ0008.md"),
+            Self::MALFORMED_IMPORT => include_str!("codes/SQ0009.md"),
+            Self::MODULE_NOT_FOUND => include_str!("codes/SQ0010.md"),
+            Self::ITEM_NOT_FOUND => include_str!("codes/SQ0011.md"),
+            Self::PRIVATE_ITEM => include_str!("codes/SQ0012.md"),
+            Self::CYCLIC_IMPORT => include_str!("codes/SQ0013.md"),
+            Self::CONFUSABLE_IDENTIFIERS => include_str!("codes/SQ0014.md"),
+            Self::SUSPICIOUS_IDENTIFIER => include_str!("codes/SQ0015.md"),
+            _ => unreachable!("error codes are only made by the constants above"),
+        }
+    }
+}
+
+impl fmt::Display for ErrorCode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "SQ{:04}", self.0)
+    }
+}
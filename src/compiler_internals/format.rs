@@ -0,0 +1,71 @@
+use crate::compiler_internals::tokenizer::{validate_source, LexError, Lexer, Token, TriviaKind};
+
+/// Blank lines kept in a row, longer runs are shortened to this.
+const MAX_BLANK_LINES: usize = 1;
+
+/// Formats `code`, working on its lossless tokens so string literals and
+/// comments are never touched.
+///
+/// There's no parser yet, so formatting only covers whitespace: trailing
+/// spaces and '\r' are removed, blank lines at the start are dropped, runs of
+/// blank lines are shortened and the file ends with exactly one newline.
+pub fn format(code: &str) -> Result<String, LexError> {
+    validate_source(code)?;
+
+    let tokens = Lexer::new(code, 0)
+        .lossless()
+        .collect::<Result<Vec<_>, _>>()?;
+
+    let mut formatted = String::with_capacity(code.len());
+    // Newlines seen since the last token or comment, `None` before any.
+    let mut newlines: Option<usize> = None;
+    // Whitespace waiting to see whether the line goes on after it.
+    let mut pending = "";
+
+    for token in &tokens {
+        for trivia in &token.trivia {
+            let text = &code[trivia.span.clone()];
+
+            match trivia.kind {
+                TriviaKind::Whitespace => pending = text,
+                TriviaKind::Newline => {
+                    pending = "";
+                    newlines = newlines.map(|count| count + 1);
+                }
+                TriviaKind::Comment => {
+                    push_line_break(&mut formatted, &mut newlines);
+                    formatted.push_str(pending);
+                    formatted.push_str(text.trim_end());
+                    pending = "";
+                }
+            }
+        }
+
+        if token.token == Token::EndOfFile {
+            break;
+        }
+
+        push_line_break(&mut formatted, &mut newlines);
+        formatted.push_str(pending);
+        formatted.push_str(&code[token.span.clone()]);
+        pending = "";
+    }
+
+    if newlines.is_some() {
+        formatted.push('\n');
+    }
+
+    Ok(formatted)
+}
+
+/// Writes the line breaks seen before the next token or comment, keeping at
+/// most [`MAX_BLANK_LINES`] blank lines, and starts counting again.
+fn push_line_break(formatted: &mut String, newlines: &mut Option<usize>) {
+    if let Some(count) = *newlines {
+        for _ in 0..count.min(MAX_BLANK_LINES + 1) {
+            formatted.push('\n');
+        }
+    }
+
+    *newlines = Some(0);
+}
@@ -0,0 +1,265 @@
+use crate::compiler_internals::tokenizer::{validate_source, LexError, Lexer, SpannedToken};
+use std::ops::Range;
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::thread;
+
+/// Source file loaded for compilation.
+pub struct SourceFile {
+    pub path: PathBuf,
+    pub code: String,
+}
+
+/// Top-level item of a file, tokenized as a single job.
+struct Job {
+    file: usize,
+    /// Byte range of the item in its file.
+    range: Range<usize>,
+}
+
+enum Scope {
+    Code,
+    String,
+    /// Inside `${...}`, with the depth of the braces opened in it.
+    Interpolation(usize),
+}
+
+/// Tokens of a file, or the first error found in it.
+pub type FileTokens = Result<Vec<SpannedToken>, LexError>;
+
+/// Tokenizes all files, spreading their top-level items over a pool of
+/// `num_cpus` workers.
+///
+/// The result doesn't depend on scheduling: there's one entry per file, in
+/// the order given, with its tokens in source order or its first error.
+pub fn tokenize_files<'a>(files: impl IntoIterator<Item = &'a SourceFile>) -> Vec<FileTokens> {
+    tokenize_files_with(&files.into_iter().collect::<Vec<_>>(), num_cpus::get())
+}
+
+fn tokenize_files_with(files: &[&SourceFile], workers: usize) -> Vec<FileTokens> {
+    let mut results: Vec<FileTokens> = Vec::new();
+    let mut jobs = Vec::new();
+
+    for (file, source) in files.iter().enumerate() {
+        match validate_source(&source.code) {
+            Ok(()) => {
+                results.push(Ok(Vec::new()));
+                jobs.extend(
+                    split_items(&source.code)
+                        .into_iter()
+                        .map(|range| Job { file, range }),
+                );
+            }
+            Err(err) => results.push(Err(err)),
+        }
+    }
+
+    let next_job = AtomicUsize::new(0);
+    let workers = workers.clamp(1, jobs.len().max(1));
+
+    let mut done: Vec<(usize, FileTokens)> = thread::scope(|scope| {
+        let handles: Vec<_> = (0..workers)
+            .map(|_| {
+                scope.spawn(|| {
+                    let mut done = Vec::new();
+
+                    loop {
+                        let index = next_job.fetch_add(1, Ordering::Relaxed);
+                        let Some(job) = jobs.get(index) else {
+                            break done;
+                        };
+
+                        let code = &files[job.file].code[job.range.clone()];
+                        let tokens = Lexer::new(code, job.range.start).collect();
+                        done.push((index, tokens));
+                    }
+                })
+            })
+            .collect();
+
+        handles
+            .into_iter()
+            .flat_map(|handle| handle.join().unwrap())
+            .collect()
+    });
+
+    done.sort_by_key(|(index, _)| *index);
+
+    for (index, tokens) in done {
+        let result = &mut results[jobs[index].file];
+
+        match (result, tokens) {
+            (Ok(file_tokens), Ok(tokens)) => file_tokens.extend(tokens),
+            (result @ Ok(_), Err(err)) => *result = Err(err),
+            // Only the first error of a file is kept.
+            (Err(_), _) => {}
+        }
+    }
+
+    results
+}
+
+/// Splits a file into the byte ranges of its top-level items (functions,
+/// types, imports...).
+///
+/// Strings, raw strings, interpolations and `//` comments are skipped over,
+/// so a `;` or `}` inside them never splits anything. Only ASCII is looked
+/// at, which is safe on UTF-8 since multi-byte characters never contain it.
+pub fn split_items(code: &str) -> Vec<Range<usize>> {
+    let bytes = code.as_bytes();
+    let mut scopes = vec![Scope::Code];
+    let mut depth: usize = 0;
+    let mut items = Vec::new();
+    let mut start = 0;
+    let mut i = 0;
+
+    while i < bytes.len() {
+        let c = bytes[i];
+        let next = bytes.get(i + 1).copied();
+
+        match scopes.last_mut().unwrap() {
+            Scope::String => match c {
+                b'\\' => i += 1,
+                b'"' => {
+                    scopes.pop();
+                }
+                b'$' if next == Some(b'{') => {
+                    i += 1;
+                    scopes.push(Scope::Interpolation(0));
+                }
+                _ => {}
+            },
+            scope => {
+                if c == b'/' && next == Some(b'/') {
+                    while i < bytes.len() && bytes[i] != b'\n' {
+                        i += 1;
+                    }
+                    continue;
+                }
+
+                if let Some(end) = raw_string_end(bytes, i) {
+                    i = end;
+                    continue;
+                }
+
+                let ends_item = match (scope, c) {
+                    (_, b'"') => {
+                        scopes.push(Scope::String);
+                        false
+                    }
+                    (Scope::Interpolation(0), b'}') => {
+                        scopes.pop();
+                        false
+                    }
+                    (Scope::Interpolation(braces), b'}') => {
+                        *braces -= 1;
+                        false
+                    }
+                    (Scope::Interpolation(braces), b'{') => {
+                        *braces += 1;
+                        false
+                    }
+                    (Scope::Interpolation(_), _) => false,
+                    (_, b'{') => {
+                        depth += 1;
+                        false
+                    }
+                    (_, b'}') => {
+                        depth = depth.saturating_sub(1);
+                        depth == 0
+                    }
+                    (_, b';') => depth == 0,
+                    _ => false,
+                };
+
+                if ends_item {
+                    items.push(start..i + 1);
+                    start = i + 1;
+                }
+            }
+        }
+
+        i += 1;
+    }
+
+    items.push(start..bytes.len());
+    items.retain(|range| !code[range.clone()].trim().is_empty());
+
+    items
+}
+
+/// Finds where a raw string starting at `start` ends, if there's one there.
+fn raw_string_end(bytes: &[u8], start: usize) -> Option<usize> {
+    // Non-ASCII bytes may belong to a name like `ár`.
+    let attached = start > 0
+        && (bytes[start - 1].is_ascii_alphanumeric()
+            || bytes[start - 1] == b'_'
+            || !bytes[start - 1].is_ascii());
+
+    if bytes[start] != b'r' || attached {
+        return None;
+    }
+
+    let hashes = bytes[start + 1..]
+        .iter()
+        .take_while(|c| **c == b'#')
+        .count();
+    let quote = start + 1 + hashes;
+
+    if bytes.get(quote) != Some(&b'"') {
+        return None;
+    }
+
+    let mut i = quote + 1;
+    while i < bytes.len() {
+        if bytes[i] == b'"' && bytes[i + 1..].iter().take_while(|c| **c == b'#').count() >= hashes {
+            return Some(i + 1 + hashes);
+        }
+        i += 1;
+    }
+
+    // Unterminated, let the lexer report it.
+    Some(bytes.len())
+}
+
+/// Compares the worker pool against a single thread on generated code.
+#[cfg(feature = "devkit")]
+pub fn bench() {
+    use std::time::Instant;
+
+    let function = r#"
+        fn item_NUMBER(a: int, b: float) -> float {
+            let [int] x = [2, 3, 4, 2];
+            let str s = "value: ${a + b}; done // not a comment";
+            if a < 10 { return b * 2.0; }
+            x.for_each((y) => { println(y); });
+            b + 1.5e3
+        }
+    "#;
+
+    let files: Vec<SourceFile> = (0..32)
+        .map(|file| SourceFile {
+            path: PathBuf::from(format!("bench_{file}.sq")),
+            code: (0..200)
+                .map(|item| function.replace("NUMBER", &item.to_string()))
+                .collect(),
+        })
+        .collect();
+
+    let lines: usize = files.iter().map(|file| file.code.lines().count()).sum();
+
+    let single = Instant::now();
+    let single_tokens = tokenize_files_with(&files.iter().collect::<Vec<_>>(), 1);
+    let single = single.elapsed();
+
+    let pool = Instant::now();
+    let pool_tokens = tokenize_files(&files);
+    let pool = pool.elapsed();
+
+    assert_eq!(single_tokens, pool_tokens);
+
+    println!("Front end benchmark: {} files, {lines} lines", files.len());
+    println!("1 worker: {single:?}");
+    println!("Worker pool ({} workers): {pool:?}", num_cpus::get());
+    println!("Speedup: {:.2}x", single.as_secs_f64() / pool.as_secs_f64());
+}
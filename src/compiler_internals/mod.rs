@@ -1,4 +1,9 @@
+pub mod format;
+pub mod frontend;
+pub mod modules;
 pub mod parser;
+pub mod stdlib;
+pub mod symbol;
 pub mod tokenizer;
 
 pub use tokenizer::*;
@@ -0,0 +1,958 @@
+use crate::codes::ErrorCode;
+use crate::compiler_internals::frontend::{tokenize_files, SourceFile};
+use crate::compiler_internals::stdlib;
+use crate::compiler_internals::symbol::Symbol;
+use crate::compiler_internals::tokenizer::{Delimiter, Keywords, LexError, SpannedToken, Token};
+use std::collections::hash_map::Entry;
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+use std::fs;
+use std::path::{Path, PathBuf};
+use unicode_security::{skeleton, GeneralSecurityProfile, MixedScript};
+
+/// Extension of Squid source files.
+pub const SOURCE_EXTENSION: &str = "sq";
+
+/// `import` statement, like `import app::math;` or `import app::math::add;`.
+#[derive(Clone)]
+pub struct Import {
+    pub path: Vec<Symbol>,
+    /// Whether the path starts with `std`.
+    pub std: bool,
+    /// Byte offset of the `import` keyword.
+    pub position: usize,
+    /// Added for the prelude, not written in the source.
+    pub implicit: bool,
+}
+
+/// What loading reads from the tokens of a module, kept by the build cache so
+/// unchanged modules don't have to be tokenized again.
+pub struct Summary {
+    /// The `import` statements written in the module.
+    pub imports: Vec<Import>,
+    pub exports: HashSet<Symbol>,
+    pub items: HashSet<Symbol>,
+    pub warnings: Vec<Warning>,
+}
+
+/// Problem found in a module that doesn't stop the build.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Warning {
+    pub code: ErrorCode,
+    pub message: String,
+    /// Byte offset in the module.
+    pub position: usize,
+}
+
+/// Package whose modules can be imported, the project or one of its path
+/// dependencies.
+pub struct Package {
+    /// Directories its import paths are relative to, searched in order.
+    pub sources: Vec<PathBuf>,
+    /// Packages it depends on by the name they're imported under, like `utils`
+    /// in `import utils::text;`, as indexes into the package list.
+    pub dependencies: HashMap<String, usize>,
+    /// Sources kept in memory by their path, used instead of the files on disk.
+    pub files: HashMap<PathBuf, String>,
+}
+
+/// Source file to load as a module.
+#[derive(Clone)]
+pub struct ModuleFile {
+    /// Path from the package root, like `app::math`.
+    pub name: String,
+    pub path: PathBuf,
+    /// Package the module belongs to, `None` for the standard library.
+    pub package: Option<usize>,
+}
+
+/// Source file loaded as a module.
+pub struct Module {
+    /// Path from the package root, like `app::math`.
+    pub name: String,
+    pub source: SourceFile,
+    /// Package the module belongs to, `None` for the standard library.
+    pub package: Option<usize>,
+    /// Empty when the module was loaded from the build cache.
+    // Nothing reads the tokens until there's a parser.
+    #[allow(dead_code)]
+    pub tokens: Vec<SpannedToken>,
+    pub imports: Vec<Import>,
+    /// Top-level items declared with `pub`.
+    pub exports: HashSet<Symbol>,
+    /// Every top-level item, public or not.
+    pub items: HashSet<Symbol>,
+    pub warnings: Vec<Warning>,
+    /// Modules this one imports, as indexes into [`ModuleGraph::modules`].
+    pub dependencies: Vec<usize>,
+}
+
+impl Module {
+    pub fn summary(&self) -> Summary {
+        Summary {
+            imports: self
+                .imports
+                .iter()
+                .filter(|import| !import.implicit)
+                .cloned()
+                .collect(),
+            exports: self.exports.clone(),
+            items: self.items.clone(),
+            warnings: self.warnings.clone(),
+        }
+    }
+}
+
+/// Every module loaded, in link order: each module comes after the ones it
+/// imports and the entry file is the last one.
+pub struct ModuleGraph {
+    pub modules: Vec<Module>,
+}
+
+impl ModuleGraph {
+    /// Warnings of every module, in link order.
+    pub fn warnings(&self) -> impl Iterator<Item = ModuleError> + '_ {
+        self.modules.iter().flat_map(|module| {
+            module.warnings.iter().map(|warning| ModuleError {
+                code: warning.code,
+                message: warning.message.clone(),
+                file: module.source.path.clone(),
+                position: warning.position,
+            })
+        })
+    }
+}
+
+/// Error found while loading or resolving modules, or one of the
+/// [`Warning`]s of a module with its file.
+#[derive(Debug, PartialEq)]
+pub struct ModuleError {
+    pub code: ErrorCode,
+    pub message: String,
+    pub file: PathBuf,
+    pub position: usize,
+}
+
+impl fmt::Display for ModuleError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{}: [{}] {} at byte {}",
+            self.file.display(),
+            self.code,
+            self.message,
+            self.position
+        )
+    }
+}
+
+impl ModuleError {
+    fn lex(file: &Path, err: LexError) -> Self {
+        Self {
+            code: err.code,
+            message: err.message,
+            file: file.to_path_buf(),
+            position: err.position,
+        }
+    }
+}
+
+/// Where an import points to.
+struct Resolved {
+    file: ModuleFile,
+    /// Item imported from the module, if it doesn't import the whole module.
+    item: Option<Symbol>,
+}
+
+/// Loads the entry file and everything it imports, with import paths
+/// relative to the directory of the entry file.
+pub fn load_modules(entry: &Path) -> Result<ModuleGraph, ModuleError> {
+    let package = Package {
+        sources: vec![entry.parent().unwrap_or(Path::new("")).to_path_buf()],
+        dependencies: HashMap::new(),
+        files: HashMap::new(),
+    };
+
+    let entry = ModuleFile {
+        name: String::from("main"),
+        path: entry.to_path_buf(),
+        package: Some(0),
+    };
+
+    load_project(&[package], vec![entry], |_| None)
+}
+
+/// Loads `files` and everything they import. The first file is the entry.
+///
+/// `import app::math;` loads `app/math.sq` from the first source directory of
+/// the importing package that has it. When there's no such file, the last
+/// segment names a `pub` item of the module before it, so
+/// `import app::math::add;` loads `app/math.sq` and checks it exports `add`.
+/// A first segment naming a dependency, like `utils` in `import utils::text;`,
+/// looks in the source directories of that package instead.
+///
+/// `import std::...` resolves the same way against the standard library
+/// embedded in the compiler, and every project module implicitly imports the
+/// [`stdlib::PRELUDE`] modules.
+///
+/// Each wave of newly found files is tokenized together on the worker pool,
+/// except for the ones `cached` has a [`Summary`] of.
+pub fn load_project(
+    packages: &[Package],
+    files: Vec<ModuleFile>,
+    cached: impl Fn(&SourceFile) -> Option<Summary>,
+) -> Result<ModuleGraph, ModuleError> {
+    let mut modules: Vec<Module> = Vec::new();
+    let mut indexes: HashMap<PathBuf, usize> = HashMap::new();
+    let mut wave = files;
+
+    for (index, file) in wave.iter().enumerate() {
+        indexes.insert(file.path.clone(), index);
+    }
+
+    while !wave.is_empty() {
+        let mut files = Vec::new();
+
+        for file in &wave {
+            let in_memory = file
+                .package
+                .and_then(|package| packages[package].files.get(&file.path));
+
+            let code = match (stdlib::source(&file.path), in_memory) {
+                (Some(code), _) => code.to_string(),
+                (None, Some(code)) => code.clone(),
+                (None, None) => fs::read_to_string(&file.path).map_err(|err| ModuleError {
+                    code: ErrorCode::UNREADABLE_FILE,
+                    message: format!("can't read the file: {err}"),
+                    file: file.path.clone(),
+                    position: 0,
+                })?,
+            };
+
+            files.push(SourceFile {
+                path: file.path.clone(),
+                code,
+            });
+        }
+
+        let first = modules.len();
+
+        let summaries: Vec<Option<Summary>> = files.iter().map(&cached).collect();
+        let mut tokenized = tokenize_files(
+            files
+                .iter()
+                .zip(&summaries)
+                .filter(|(_, summary)| summary.is_none())
+                .map(|(source, _)| source),
+        )
+        .into_iter();
+
+        for (file, (source, summary)) in wave.drain(..).zip(files.into_iter().zip(summaries)) {
+            let (summary, tokens) = match summary {
+                Some(summary) => (summary, Vec::new()),
+                None => {
+                    let tokens = tokenized
+                        .next()
+                        .unwrap()
+                        .map_err(|err| ModuleError::lex(&source.path, err))?;
+                    let (items, exports) = declarations(&tokens);
+                    let imports = imports(&source, &tokens)?;
+
+                    (
+                        Summary {
+                            imports,
+                            exports,
+                            items,
+                            warnings: identifier_warnings(&tokens),
+                        },
+                        tokens,
+                    )
+                }
+            };
+
+            let mut imports = summary.imports;
+
+            if file.package.is_some() {
+                imports.extend(stdlib::PRELUDE.iter().map(|name| Import {
+                    path: vec![Symbol::intern("std"), Symbol::intern(name)],
+                    std: true,
+                    position: 0,
+                    implicit: true,
+                }));
+            }
+
+            modules.push(Module {
+                name: file.name,
+                package: file.package,
+                imports,
+                source,
+                tokens,
+                exports: summary.exports,
+                items: summary.items,
+                warnings: summary.warnings,
+                dependencies: Vec::new(),
+            });
+        }
+
+        for module in &modules[first..] {
+            for import in &module.imports {
+                let resolved = resolve(packages, module, import)?;
+
+                if !indexes.contains_key(&resolved.file.path) {
+                    // Files of the next wave get the indexes after this one.
+                    indexes.insert(resolved.file.path.clone(), modules.len() + wave.len());
+                    wave.push(resolved.file);
+                }
+            }
+        }
+    }
+
+    for index in 0..modules.len() {
+        let mut dependencies = Vec::new();
+
+        for import in &modules[index].imports {
+            let resolved = resolve(packages, &modules[index], import)?;
+            let dependency = indexes[&resolved.file.path];
+
+            if let Some(item) = resolved.item {
+                check_visibility(&modules[index], &modules[dependency], import, item)?;
+            }
+
+            dependencies.push(dependency);
+        }
+
+        modules[index].dependencies = dependencies;
+    }
+
+    let order = link_order(&modules)?;
+
+    Ok(ModuleGraph {
+        modules: reorder(modules, &order),
+    })
+}
+
+/// Finds the module an import points to.
+fn resolve(
+    packages: &[Package],
+    module: &Module,
+    import: &Import,
+) -> Result<Resolved, ModuleError> {
+    let segments: Vec<&str> = import.path.iter().map(|s| s.as_str()).collect();
+
+    if import.std {
+        return resolve_std(module, import, &segments[1..]);
+    }
+
+    let error = |message: String| ModuleError {
+        code: ErrorCode::MODULE_NOT_FOUND,
+        message,
+        file: module.source.path.clone(),
+        position: import.position,
+    };
+
+    let Some(package) = module.package else {
+        return Err(error(String::from(
+            "the standard library can only import 'std' modules",
+        )));
+    };
+
+    // `import utils::text;` looks in the `utils` dependency.
+    let (package, prefix, path) = match packages[package].dependencies.get(segments[0]) {
+        Some(&dependency) if segments.len() > 1 => (dependency, Some(segments[0]), &segments[1..]),
+        _ => (package, None, &segments[..]),
+    };
+
+    let module_file = |segments: &[&str]| {
+        let name = match prefix {
+            Some(prefix) => format!("{prefix}::{}", segments.join("::")),
+            None => segments.join("::"),
+        };
+
+        packages[package].sources.iter().find_map(|source| {
+            let mut path = source.clone();
+            path.extend(segments);
+            path.set_extension(SOURCE_EXTENSION);
+
+            (packages[package].files.contains_key(&path) || path.is_file()).then(|| ModuleFile {
+                name: name.clone(),
+                path,
+                package: Some(package),
+            })
+        })
+    };
+
+    if let Some(file) = module_file(path) {
+        return Ok(Resolved { file, item: None });
+    }
+
+    // `import app::math::add;` imports an item of `app::math`.
+    if path.len() > 1 {
+        if let Some(file) = module_file(&path[..path.len() - 1]) {
+            return Ok(Resolved {
+                file,
+                item: import.path.last().cloned(),
+            });
+        }
+    }
+
+    let expected: Vec<String> = packages[package]
+        .sources
+        .iter()
+        .map(|source| {
+            let mut file = source.clone();
+            file.extend(path);
+            format!("'{}'", file.with_extension(SOURCE_EXTENSION).display())
+        })
+        .collect();
+
+    Err(error(format!(
+        "can't find module '{}', expected it at {}",
+        segments.join("::"),
+        expected.join(" or ")
+    )))
+}
+
+/// Like [`resolve`], for the `segments` after `std::`.
+fn resolve_std(
+    module: &Module,
+    import: &Import,
+    segments: &[&str],
+) -> Result<Resolved, ModuleError> {
+    let module_file = |segments: &[&str]| {
+        let path = stdlib::path(&segments.join("/"));
+
+        (!segments.is_empty() && stdlib::source(&path).is_some()).then(|| ModuleFile {
+            name: format!("std::{}", segments.join("::")),
+            path,
+            package: None,
+        })
+    };
+
+    if let Some(file) = module_file(segments) {
+        return Ok(Resolved { file, item: None });
+    }
+
+    // `import std::io::println;` imports an item of `std::io`.
+    if segments.len() > 1 {
+        if let Some(file) = module_file(&segments[..segments.len() - 1]) {
+            return Ok(Resolved {
+                file,
+                item: import.path.last().cloned(),
+            });
+        }
+    }
+
+    Err(ModuleError {
+        code: ErrorCode::MODULE_NOT_FOUND,
+        message: format!(
+            "the standard library has no module 'std::{}'",
+            segments.join("::")
+        ),
+        file: module.source.path.clone(),
+        position: import.position,
+    })
+}
+
+fn check_visibility(
+    module: &Module,
+    dependency: &Module,
+    import: &Import,
+    item: Symbol,
+) -> Result<(), ModuleError> {
+    if dependency.exports.contains(&item) {
+        return Ok(());
+    }
+
+    let (code, message) = if dependency.items.contains(&item) {
+        (
+            ErrorCode::PRIVATE_ITEM,
+            format!(
+                "'{item}' is private to module '{}', declare it with 'pub' to import it",
+                dependency.name
+            ),
+        )
+    } else {
+        (
+            ErrorCode::ITEM_NOT_FOUND,
+            format!("module '{}' has no item named '{item}'", dependency.name),
+        )
+    };
+
+    Err(ModuleError {
+        code,
+        message,
+        file: module.source.path.clone(),
+        position: import.position,
+    })
+}
+
+/// Warns about identifiers that can be mistaken for others (UTS #39): names
+/// mixing scripts or using restricted characters, and different names that
+/// look the same, like `password` and `pаssword` with a Cyrillic 'а'.
+///
+/// Names look the same when their confusable skeletons are equal. ASCII ones
+/// aren't compared with each other, `rn` and `m` are different enough in code.
+fn identifier_warnings(tokens: &[SpannedToken]) -> Vec<Warning> {
+    let mut warnings = Vec::new();
+    let mut seen = HashSet::new();
+    // First name seen with each skeleton, and where.
+    let mut skeletons: HashMap<String, (&Symbol, usize)> = HashMap::new();
+
+    for token in tokens {
+        let Token::Identifier(name) = &token.token else {
+            continue;
+        };
+
+        if !seen.insert(name) {
+            continue;
+        }
+
+        let position = token.span.start;
+        let text = name.as_str();
+
+        if !text.is_single_script() {
+            warnings.push(Warning {
+                code: ErrorCode::SUSPICIOUS_IDENTIFIER,
+                message: format!("'{name}' mixes scripts"),
+                position,
+            });
+        } else if !text.chars().all(|c| c.identifier_allowed()) {
+            warnings.push(Warning {
+                code: ErrorCode::SUSPICIOUS_IDENTIFIER,
+                message: format!("'{name}' uses characters restricted in identifiers"),
+                position,
+            });
+        }
+
+        match skeletons.entry(skeleton(text).collect()) {
+            Entry::Vacant(entry) => {
+                entry.insert((name, position));
+            }
+            Entry::Occupied(entry) => {
+                let (other, other_position) = *entry.get();
+
+                if !text.is_ascii() || !other.as_str().is_ascii() {
+                    warnings.push(Warning {
+                        code: ErrorCode::CONFUSABLE_IDENTIFIERS,
+                        message: format!(
+                            "'{name}' can be mistaken for '{other}' (byte {other_position})"
+                        ),
+                        position,
+                    });
+                }
+            }
+        }
+    }
+
+    warnings
+}
+
+/// Reads the `import` statements at the top level of a module.
+fn imports(source: &SourceFile, tokens: &[SpannedToken]) -> Result<Vec<Import>, ModuleError> {
+    let file = &source.path;
+    let mut imports = Vec::new();
+    let mut depth = 0;
+    let mut i = 0;
+
+    while i < tokens.len() {
+        match &tokens[i].token {
+            Token::Delimiter(Delimiter::OpenObject) => depth += 1,
+            Token::Delimiter(Delimiter::CloseObject) => depth -= 1,
+            Token::Keyword(Keywords::Import) if depth == 0 => {
+                let position = tokens[i].span.start;
+                let mut path = Vec::new();
+                let mut std = false;
+
+                i += 1;
+
+                loop {
+                    match tokens.get(i).map(|t| &t.token) {
+                        Some(Token::Keyword(Keywords::StandardLibrary)) if path.is_empty() => {
+                            std = true;
+                            path.push(Symbol::intern("std"));
+                        }
+                        Some(Token::Identifier(name)) => path.push(name.clone()),
+                        // Modules can share the name of a type, like `std::array`.
+                        Some(Token::Types(_)) if !path.is_empty() => {
+                            let span = tokens[i].span.clone();
+                            path.push(Symbol::intern(&source.code[span]));
+                        }
+                        _ => {
+                            return Err(ModuleError {
+                                code: ErrorCode::MALFORMED_IMPORT,
+                                message: String::from("expected a module name in 'import'"),
+                                file: file.clone(),
+                                position: tokens.get(i).map_or(position, |t| t.span.start),
+                            })
+                        }
+                    }
+
+                    i += 1;
+
+                    match tokens.get(i).map(|t| &t.token) {
+                        Some(Token::Delimiter(Delimiter::ImportSeparator)) => i += 1,
+                        Some(Token::Delimiter(Delimiter::Semicolon)) => break,
+                        _ => {
+                            return Err(ModuleError {
+                                code: ErrorCode::MALFORMED_IMPORT,
+                                message: String::from("expected '::' or ';' in 'import'"),
+                                file: file.clone(),
+                                position: tokens.get(i).map_or(position, |t| t.span.start),
+                            })
+                        }
+                    }
+                }
+
+                imports.push(Import {
+                    path,
+                    std,
+                    position,
+                    implicit: false,
+                });
+            }
+            _ => {}
+        }
+
+        i += 1;
+    }
+
+    Ok(imports)
+}
+
+/// Names of the top-level functions, types, interfaces and bindings of a
+/// module, and of the ones among them declared with `pub`.
+fn declarations(tokens: &[SpannedToken]) -> (HashSet<Symbol>, HashSet<Symbol>) {
+    let mut items = HashSet::new();
+    let mut exports = HashSet::new();
+    let mut depth = 0;
+
+    for (i, token) in tokens.iter().enumerate() {
+        match &token.token {
+            Token::Delimiter(Delimiter::OpenObject) => depth += 1,
+            Token::Delimiter(Delimiter::CloseObject) => depth -= 1,
+            Token::Keyword(keyword) if depth == 0 => {
+                let rest = tokens[i + 1..].iter().map(|t| &t.token);
+
+                let name = match keyword {
+                    // `fn name`, `type name` or `type fn name`.
+                    Keywords::Function | Keywords::Type | Keywords::Interface => rest
+                        .take_while(|t| matches!(t, Token::Keyword(_) | Token::Identifier(_)))
+                        .find_map(|t| match t {
+                            Token::Identifier(name) => Some(name.clone()),
+                            _ => None,
+                        }),
+                    // `let [int] x = ...;`, the type comes before the name.
+                    Keywords::Let | Keywords::Const => rest
+                        .take_while(|t| {
+                            !matches!(
+                                t,
+                                Token::Operator(_) | Token::Delimiter(Delimiter::Semicolon)
+                            )
+                        })
+                        .filter_map(|t| match t {
+                            Token::Identifier(name) => Some(name.clone()),
+                            _ => None,
+                        })
+                        .last(),
+                    _ => None,
+                };
+
+                let Some(name) = name else {
+                    continue;
+                };
+
+                let public = i > 0 && tokens[i - 1].token == Token::Keyword(Keywords::Public);
+                if public {
+                    exports.insert(name.clone());
+                }
+
+                items.insert(name);
+            }
+            _ => {}
+        }
+    }
+
+    (items, exports)
+}
+
+/// Orders modules so each one comes after its dependencies, failing on the
+/// first cyclic import.
+fn link_order(modules: &[Module]) -> Result<Vec<usize>, ModuleError> {
+    #[derive(Clone, Copy, PartialEq)]
+    enum State {
+        New,
+        Visiting,
+        Done,
+    }
+
+    fn visit(
+        index: usize,
+        modules: &[Module],
+        states: &mut [State],
+        stack: &mut Vec<usize>,
+        order: &mut Vec<usize>,
+    ) -> Result<(), ModuleError> {
+        states[index] = State::Visiting;
+        stack.push(index);
+
+        let module = &modules[index];
+
+        for (import, &dependency) in module.imports.iter().zip(&module.dependencies) {
+            match states[dependency] {
+                State::Done => {}
+                State::New => visit(dependency, modules, states, stack, order)?,
+                State::Visiting => {
+                    let start = stack.iter().position(|i| *i == dependency).unwrap();
+                    let cycle: Vec<&str> = stack[start..]
+                        .iter()
+                        .chain([&dependency])
+                        .map(|i| modules[*i].name.as_str())
+                        .collect();
+
+                    return Err(ModuleError {
+                        code: ErrorCode::CYCLIC_IMPORT,
+                        message: format!("cyclic import: {}", cycle.join(" -> ")),
+                        file: module.source.path.clone(),
+                        position: import.position,
+                    });
+                }
+            }
+        }
+
+        stack.pop();
+        states[index] = State::Done;
+        order.push(index);
+
+        Ok(())
+    }
+
+    let mut states = vec![State::New; modules.len()];
+    let mut order = Vec::new();
+
+    // The entry file is module 0, it goes last unless something imports it.
+    for index in (1..modules.len()).chain([0]) {
+        if states[index] == State::New {
+            visit(index, modules, &mut states, &mut Vec::new(), &mut order)?;
+        }
+    }
+
+    Ok(order)
+}
+
+/// Puts modules in `order`, renumbering their dependencies.
+fn reorder(modules: Vec<Module>, order: &[usize]) -> Vec<Module> {
+    let mut new_index = vec![0; modules.len()];
+    for (new, old) in order.iter().enumerate() {
+        new_index[*old] = new;
+    }
+
+    let mut modules: Vec<Option<Module>> = modules.into_iter().map(Some).collect();
+
+    order
+        .iter()
+        .map(|old| {
+            let mut module = modules[*old].take().unwrap();
+            for dependency in &mut module.dependencies {
+                *dependency = new_index[*dependency];
+            }
+            module
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Loads `main.sq` from a package kept in memory, with `utils` as a
+    /// dependency holding `utils_files`.
+    fn load(
+        files: &[(&str, &str)],
+        utils_files: &[(&str, &str)],
+    ) -> Result<ModuleGraph, ModuleError> {
+        let package = |root: &str, files: &[(&str, &str)], dependencies| Package {
+            sources: vec![PathBuf::from(root)],
+            dependencies,
+            files: files
+                .iter()
+                .map(|(path, code)| (Path::new(root).join(path), code.to_string()))
+                .collect(),
+        };
+
+        let packages = [
+            package("/app", files, HashMap::from([(String::from("utils"), 1)])),
+            package("/utils", utils_files, HashMap::new()),
+        ];
+        let entry = ModuleFile {
+            name: String::from("main"),
+            path: PathBuf::from("/app/main.sq"),
+            package: Some(0),
+        };
+
+        load_project(&packages, vec![entry], |_| None)
+    }
+
+    /// Names of the modules of `graph` in link order, without the standard library.
+    fn names(graph: &ModuleGraph) -> Vec<&str> {
+        graph
+            .modules
+            .iter()
+            .map(|module| module.name.as_str())
+            .filter(|name| !name.starts_with("std::"))
+            .collect()
+    }
+
+    #[test]
+    fn modules_come_after_their_imports() {
+        let graph = load(
+            &[
+                (
+                    "main.sq",
+                    "import app::b;\nimport app::a::one;\nfn main() {}",
+                ),
+                ("app/a.sq", "pub fn one() {}"),
+                ("app/b.sq", "import utils::text;\nimport app::a;"),
+            ],
+            &[("text.sq", "pub fn upper() {}")],
+        )
+        .unwrap();
+
+        assert_eq!(
+            names(&graph),
+            vec!["utils::text", "app::a", "app::b", "main"]
+        );
+        assert_eq!(graph.modules.last().unwrap().name, "main");
+
+        for (index, module) in graph.modules.iter().enumerate() {
+            assert!(module
+                .dependencies
+                .iter()
+                .all(|dependency| *dependency < index));
+        }
+    }
+
+    #[test]
+    fn cycles_are_reported_with_their_path() {
+        let err = load(
+            &[
+                ("main.sq", "import app::a;"),
+                ("app/a.sq", "import app::b;"),
+                ("app/b.sq", "\nimport app::a;"),
+            ],
+            &[],
+        )
+        .err()
+        .expect("the modules loaded");
+
+        assert_eq!(err.code, ErrorCode::CYCLIC_IMPORT);
+        assert_eq!(err.message, "cyclic import: app::a -> app::b -> app::a");
+    }
+
+    #[test]
+    fn missing_modules_list_where_they_were_expected() {
+        let err = load(&[("main.sq", "fn main() {}\nimport app::nope;")], &[])
+            .err()
+            .expect("the modules loaded");
+
+        assert_eq!(err.code, ErrorCode::MODULE_NOT_FOUND);
+        assert_eq!(
+            err.message,
+            "can't find module 'app::nope', expected it at '/app/app/nope.sq'"
+        );
+        assert_eq!(err.file, Path::new("/app/main.sq"));
+        assert_eq!(err.position, 13);
+
+        let err = load(&[("main.sq", "import std::nope;")], &[])
+            .err()
+            .expect("the modules loaded");
+        assert_eq!(err.code, ErrorCode::MODULE_NOT_FOUND);
+    }
+
+    #[test]
+    fn private_and_missing_items() {
+        let files = |main| {
+            [
+                ("main.sq", main),
+                ("app/math.sq", "fn hidden() {}\npub fn add() {}"),
+            ]
+        };
+
+        assert!(load(&files("import app::math::add;"), &[]).is_ok());
+
+        let err = load(&files("import app::math::hidden;"), &[])
+            .err()
+            .expect("the modules loaded");
+        assert_eq!(err.code, ErrorCode::PRIVATE_ITEM);
+        assert_eq!(
+            err.message,
+            "'hidden' is private to module 'app::math', declare it with 'pub' to import it"
+        );
+
+        let err = load(&files("import app::math::nope;"), &[])
+            .err()
+            .expect("the modules loaded");
+        assert_eq!(err.code, ErrorCode::ITEM_NOT_FOUND);
+        assert_eq!(err.message, "module 'app::math' has no item named 'nope'");
+    }
+
+    #[test]
+    fn names_that_look_alike_are_warned_about() {
+        let graph = load(
+            &[(
+                "main.sq",
+                "let str password = 1;\nlet str p\u{430}ssword = 2;\nlet int c\u{3BF}unt = 0;\n\
+                 let int rn = m;\nlet str a\u{E7}\u{E3}o = password;",
+            )],
+            &[],
+        )
+        .unwrap();
+
+        let warnings: Vec<_> = graph
+            .warnings()
+            .map(|warning| (warning.code, warning.message, warning.position))
+            .collect();
+
+        assert_eq!(
+            warnings,
+            vec![
+                (
+                    ErrorCode::SUSPICIOUS_IDENTIFIER,
+                    String::from("'p\u{430}ssword' mixes scripts"),
+                    30
+                ),
+                (
+                    ErrorCode::CONFUSABLE_IDENTIFIERS,
+                    String::from("'p\u{430}ssword' can be mistaken for 'password' (byte 8)"),
+                    30
+                ),
+                (
+                    ErrorCode::SUSPICIOUS_IDENTIFIER,
+                    String::from("'c\u{3BF}unt' mixes scripts"),
+                    53
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn single_letters_of_other_scripts_are_compared() {
+        let graph = load(&[("main.sq", "let int \u{430} = a;")], &[]).unwrap();
+        let warning = graph.warnings().next().unwrap();
+
+        assert_eq!(warning.code, ErrorCode::CONFUSABLE_IDENTIFIERS);
+        assert_eq!(
+            warning.message,
+            "'a' can be mistaken for '\u{430}' (byte 8)"
+        );
+    }
+
+    #[test]
+    fn malformed_imports() {
+        for code in ["import app::;", "import app", "import ;"] {
+            let err = load(&[("main.sq", code)], &[])
+                .err()
+                .expect("the modules loaded");
+            assert_eq!(err.code, ErrorCode::MALFORMED_IMPORT, "{code}");
+        }
+    }
+}
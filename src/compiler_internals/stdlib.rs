@@ -0,0 +1,33 @@
+use crate::compiler_internals::modules::SOURCE_EXTENSION;
+use std::path::{Path, PathBuf};
+
+/// Directory the standard library modules are shown under in paths and errors.
+pub const ROOT: &str = "<std>";
+
+/// Modules imported by every module without an `import`.
+pub const PRELUDE: [&str; 1] = ["io"];
+
+/// Squid sources of the standard library, embedded in the compiler.
+const MODULES: [(&str, &str); 5] = [
+    ("io", include_str!("../../std/io.sq")),
+    ("string", include_str!("../../std/string.sq")),
+    ("math", include_str!("../../std/math.sq")),
+    ("array", include_str!("../../std/array.sq")),
+    ("result", include_str!("../../std/result.sq")),
+];
+
+/// Path of the standard library module `name`, like `<std>/io.sq` for `io`.
+pub fn path(name: &str) -> PathBuf {
+    Path::new(ROOT).join(name).with_extension(SOURCE_EXTENSION)
+}
+
+/// Source of the standard library module at `path`, if there's one.
+pub fn source(path: &Path) -> Option<&'static str> {
+    let name = path.strip_prefix(ROOT).ok()?.with_extension("");
+    let name = name.to_str()?;
+
+    MODULES
+        .iter()
+        .find(|(module, _)| *module == name)
+        .map(|(_, code)| *code)
+}
@@ -0,0 +1,144 @@
+use std::collections::HashSet;
+use std::fmt;
+use std::hash::{Hash, Hasher};
+use std::sync::{Arc, OnceLock, RwLock};
+
+/// Interned string, like an identifier or the contents of a string literal.
+///
+/// The same text always gives the same symbol, so comparing two of them is a
+/// single pointer comparison and equal strings are only stored once.
+#[derive(Clone)]
+pub struct Symbol(Arc<str>);
+
+/// Size the interner can grow to before it first drops unused strings.
+const MIN_PURGE_SIZE: usize = 4096;
+
+/// Table of the strings interned so far, shared by all threads.
+///
+/// It only keeps strings alive while a symbol uses them: once it has doubled
+/// in size, the strings no symbol points to anymore are dropped. That keeps
+/// long-running compilers, like `build --watch` or an embedding [`Session`],
+/// from growing with every edit.
+///
+/// [`Session`]: crate::Session
+struct Interner {
+    strings: HashSet<Arc<str>>,
+    /// Size at which unused strings are dropped next.
+    purge_at: usize,
+}
+
+impl Interner {
+    fn intern(&mut self, text: &str) -> Symbol {
+        if let Some(string) = self.strings.get(text) {
+            return Symbol(string.clone());
+        }
+
+        if self.strings.len() >= self.purge_at {
+            // The table holds the only reference to strings no symbol uses.
+            self.strings.retain(|string| Arc::strong_count(string) > 1);
+            self.purge_at = (self.strings.len() * 2).max(MIN_PURGE_SIZE);
+        }
+
+        let string: Arc<str> = Arc::from(text);
+        self.strings.insert(string.clone());
+
+        Symbol(string)
+    }
+}
+
+fn interner() -> &'static RwLock<Interner> {
+    static INTERNER: OnceLock<RwLock<Interner>> = OnceLock::new();
+    INTERNER.get_or_init(|| {
+        RwLock::new(Interner {
+            strings: HashSet::new(),
+            purge_at: MIN_PURGE_SIZE,
+        })
+    })
+}
+
+impl Symbol {
+    pub fn intern(text: &str) -> Self {
+        // Most lookups find a symbol that's already there, and those only need
+        // the shared lock, so workers lexing in parallel don't wait on each other.
+        if let Some(string) = interner().read().unwrap().strings.get(text) {
+            return Symbol(string.clone());
+        }
+
+        interner().write().unwrap().intern(text)
+    }
+
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+}
+
+impl PartialEq for Symbol {
+    fn eq(&self, other: &Self) -> bool {
+        Arc::ptr_eq(&self.0, &other.0)
+    }
+}
+
+impl Eq for Symbol {}
+
+impl Hash for Symbol {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        Arc::as_ptr(&self.0).cast::<u8>().hash(state);
+    }
+}
+
+impl PartialOrd for Symbol {
+    fn partial_cmp(&self, other: &Self) -> Option<std::cmp::Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+/// Alphabetical, equal symbols are the same string anyway.
+impl Ord for Symbol {
+    fn cmp(&self, other: &Self) -> std::cmp::Ordering {
+        self.as_str().cmp(other.as_str())
+    }
+}
+
+impl fmt::Debug for Symbol {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:?}", self.as_str())
+    }
+}
+
+impl fmt::Display for Symbol {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn same_text_gives_the_same_symbol() {
+        let a = Symbol::intern("symbol_test_name");
+        let b = Symbol::intern(&String::from("symbol_test_name"));
+
+        assert_eq!(a, b);
+        assert!(Arc::ptr_eq(&a.0, &b.0));
+        assert_ne!(a, Symbol::intern("symbol_test_other"));
+        assert_eq!(b.as_str(), "symbol_test_name");
+    }
+
+    #[test]
+    fn unused_strings_are_dropped() {
+        let kept = Symbol::intern("symbol_test_kept");
+
+        for i in 0..MIN_PURGE_SIZE * 4 {
+            Symbol::intern(&format!("symbol_test_unused_{i}"));
+        }
+
+        let size = interner().read().unwrap().strings.len();
+        assert!(
+            size < MIN_PURGE_SIZE * 3,
+            "{size} strings are still interned"
+        );
+        assert_eq!(kept, Symbol::intern("symbol_test_kept"));
+    }
+}
@@ -1,12 +1,17 @@
-use async_std::task;
-use async_std::task::JoinHandle;
+use crate::codes::ErrorCode;
+use crate::compiler_internals::symbol::Symbol;
+use std::borrow::Cow;
+use std::collections::VecDeque;
+use std::fmt;
+use std::num::IntErrorKind;
+use std::ops::Range;
+use unicode_normalization::{is_nfc, UnicodeNormalization};
+use unicode_xid::UnicodeXID;
 
 #[derive(Debug, PartialEq)]
 pub enum Keywords {
     Let,
     Const,
-    Print,
-    PrintLine,
     Loop,
     While,
     For,
@@ -18,6 +23,17 @@ pub enum Keywords {
     New,
     Import,
     StandardLibrary,
+    Interface,
+    Implement,
+    SelfValue,
+    Return,
+    Break,
+    Continue,
+    In,
+    As,
+    Match,
+    Public,
+    Mutable,
 }
 
 use Keywords::*;
@@ -40,6 +56,10 @@ pub enum Operators {
     LessThan,
     /// Greater than operator '>'
     GreaterThan,
+    /// Error propagation operator '?'
+    ///
+    /// Unwraps `Ok` and returns `Err`/`ErrCode` from the enclosing function.
+    Propagate,
 }
 
 use Operators::*;
@@ -70,7 +90,7 @@ pub enum Types {
     ///
     /// let [uint] x = [ 10, 21, 41, 743, 12 ];
     ///
-    /// Array of unsigned integers
+    /// Array of unsigned integers, also written as `array<uint>`
     Array,
 
     Null,
@@ -95,9 +115,14 @@ pub enum Delimiter {
     /// Close Object '}'
     CloseObject,
     /// String marking '"'
+    #[allow(dead_code)]
     QuoteString,
     /// Separate different items
     Comma,
+    /// Declares the type of a record field ':'
+    ///
+    /// Example: type Point { x: float, y: float }
+    Colon,
     /// Declarates function return type '->'
     TypeArrow,
     /// Makes a function using '=>'
@@ -105,8 +130,11 @@ pub enum Delimiter {
     /// Example: x.foreach((y) => { print(y); });
     FunctionArrow,
 
+    /// Opens a type parameter list '<'
+    ///
+    /// Example: fn first<T>(xs: [T]) -> T
     OpenTypeParams,
-
+    /// Closes a type parameter list '>'
     CloseTypeParams,
 
     /// The object separator, for getting values and calling methods '.'
@@ -125,536 +153,1469 @@ pub enum Delimiter {
 
 use Delimiter::*;
 
+/// Token read from the source.
+///
+/// Identifiers and strings are interned, so comparing them is cheap and
+/// repeated ones are stored once.
 #[derive(Debug, PartialEq)]
 pub enum Token {
     Keyword(Keywords),
     Types(Types),
     Operator(Operators),
     Delimiter(Delimiter),
-    Identifier(String),
-    LiteralString(String),
+    Identifier(Symbol),
+    LiteralString(Symbol),
     LiteralInteger(i64),
     LiteralUInteger(u64),
     LiteralFloat(f64),
+    LiteralBool(bool),
+    /// End of the source, only produced in lossless mode to carry the
+    /// trivia after the last token.
+    EndOfFile,
 }
 
-pub fn test() {
-    // let code = r#"
-    //
-    // type fn mainResult() -> { Ok(null), { Err(str) ErrCode(uint) } }
-    //
-    // fn main() -> mainResult {
-    //     let [int] x = [2, 3, 4, 2];
-    //     let int y = 2;
-    //     let [bool] z;
-    //
-    //     x.for_each((a) => {
-    //         println(a);
-    //
-    //         let bool result = y == a;
-    //
-    //         if result {
-    //             z.push(result);
-    //         }
-    //     });
-    //
-    //     println("Right combination: ${z}");
-    //
-    //     Ok(null)
-    //
-    // }
-    //
-    // "#
-    // .to_string();
-
-    let code = r#"
+impl Keywords {
+    /// The keyword as written in the source.
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Let => "let",
+            Const => "const",
+            Loop => "loop",
+            While => "while",
+            For => "for",
+            Function => "fn",
+            Type => "type",
+            If => "if",
+            Else => "else",
+            Main => "main",
+            New => "new",
+            Import => "import",
+            StandardLibrary => "std",
+            Interface => "interface",
+            Implement => "impl",
+            SelfValue => "self",
+            Return => "return",
+            Break => "break",
+            Continue => "continue",
+            In => "in",
+            As => "as",
+            Match => "match",
+            Public => "pub",
+            Mutable => "mut",
+        }
+    }
+}
 
-        fn main() {
+impl Types {
+    /// The type name as written in the source.
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Bool => "bool",
+            Int => "int",
+            UInt => "uint",
+            Float => "float",
+            StringType => "str",
+            Array => "array",
+            Null => "null",
+        }
+    }
+}
 
-            println("Hello, World!");
+impl Operators {
+    /// The operator as written in the source.
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Add => "+",
+            Subtract => "-",
+            Multiply => "*",
+            Divide => "/",
+            Assign => "=",
+            Equals => "==",
+            LessThan => "<",
+            GreaterThan => ">",
+            Propagate => "?",
+        }
+    }
+}
 
+impl Delimiter {
+    /// The delimiter as written in the source. Raw strings open with `r"` or
+    /// `r#"` and close with `"#`, they're still shown as `"`.
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Semicolon => ";",
+            OpenSequence => "(",
+            CloseSequence => ")",
+            OpenArray => "[",
+            CloseArray => "]",
+            OpenObject => "{",
+            CloseObject => "}",
+            QuoteString | OpenString | CloseString => "\"",
+            Comma => ",",
+            Colon => ":",
+            TypeArrow => "->",
+            FunctionArrow => "=>",
+            OpenTypeParams => "<",
+            CloseTypeParams => ">",
+            ObjectSeparator => ".",
+            ImportSeparator => "::",
+            OpenInterpolation => "${",
+            CloseInterpolation => "}",
         }
+    }
+}
 
-    "#
-    .to_string();
+/// One line of the `--emit tokens` dump: the kind of token, a space and its
+/// value, like `keyword fn`, `identifier main`, `delimiter (` or `int 42`.
+///
+/// Strings are quoted and escaped like Rust strings, `string "a\nb"`, and
+/// floats always have a decimal point, `float 2.0`. The end of the source is
+/// `eof`. Keywords, types, operators and delimiters are shown as written in
+/// the source, so tools can parse the dump without knowing the compiler.
+impl fmt::Display for Token {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Token::Keyword(keyword) => write!(f, "keyword {}", keyword.as_str()),
+            Token::Types(types) => write!(f, "type {}", types.as_str()),
+            Token::Operator(operator) => write!(f, "operator {}", operator.as_str()),
+            Token::Delimiter(delimiter) => write!(f, "delimiter {}", delimiter.as_str()),
+            Token::Identifier(name) => write!(f, "identifier {name}"),
+            Token::LiteralString(text) => write!(f, "string \"{}\"", text.as_str().escape_debug()),
+            Token::LiteralInteger(value) => write!(f, "int {value}"),
+            Token::LiteralUInteger(value) => write!(f, "uint {value}"),
+            Token::LiteralFloat(value) => write!(f, "float {value:?}"),
+            Token::LiteralBool(value) => write!(f, "bool {value}"),
+            Token::EndOfFile => f.write_str("eof"),
+        }
+    }
+}
 
-    let code_without_comments = remove_comments(&code);
+#[derive(Debug, PartialEq)]
+pub enum TriviaKind {
+    /// Spaces, tabs, '\r' and the byte order mark
+    Whitespace,
+    /// A single '\n'
+    Newline,
+    /// A `//` comment, without the '\n' ending it
+    Comment,
+}
 
-    // println!("{code_without_comments}");
+/// Source skipped between tokens, kept for tools like formatters.
+#[derive(Debug, PartialEq)]
+pub struct Trivia {
+    pub kind: TriviaKind,
+    pub span: Range<usize>,
+}
 
-    let chuncks = code_chunckenizer(code_without_comments);
-    let mut futures = Vec::new();
+/// Token with the byte range of the source it was read from.
+#[derive(Debug, PartialEq)]
+pub struct SpannedToken {
+    pub token: Token,
+    pub span: Range<usize>,
+    /// Trivia right before the token, always empty unless the lexer is lossless.
+    pub trivia: Vec<Trivia>,
+}
 
-    async fn task_tokenize(chunck: String) -> Vec<Token> {
-        tokenize(chunck)
+impl SpannedToken {
+    /// Span of the token including its leading trivia.
+    ///
+    /// In lossless mode these spans cover the whole source with no gaps.
+    // Only tooling needs trivia, the compiler itself doesn't use it yet.
+    #[allow(dead_code)]
+    pub fn full_span(&self) -> Range<usize> {
+        let start = self
+            .trivia
+            .first()
+            .map_or(self.span.start, |t| t.span.start);
+        start..self.span.end
     }
+}
 
-    for chunck in chuncks {
-        let future = task::spawn(task_tokenize(chunck));
-        futures.push(future);
-    }
+/// Bidirectional control characters, they can make code read differently from
+/// how it's compiled ("Trojan Source", CVE-2021-42574).
+const BIDI_CONTROLS: [char; 12] = [
+    '\u{061C}', '\u{200E}', '\u{200F}', '\u{202A}', '\u{202B}', '\u{202C}', '\u{202D}', '\u{202E}',
+    '\u{2066}', '\u{2067}', '\u{2068}', '\u{2069}',
+];
 
-    let mut tokens = Vec::new();
-    let mut counter_chunks = 0;
+/// Words kept for future language features, they can't be used as identifiers.
+pub const RESERVED_WORDS: [&str; 10] = [
+    "enum", "trait", "async", "await", "yield", "static", "where", "super", "macro", "try",
+];
 
-    for future in futures {
-        counter_chunks += 1;
-        async fn idk(future: JoinHandle<Vec<Token>>) -> Vec<Token> {
-            future.await
-        }
+/// Error found while tokenizing, with the byte offset that caused it.
+#[derive(Debug, PartialEq)]
+pub struct LexError {
+    pub code: ErrorCode,
+    pub message: String,
+    pub position: usize,
+}
 
-        tokens.extend(task::block_on(idk(future)));
+impl fmt::Display for LexError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "[{}] {} at byte {}",
+            self.code, self.message, self.position
+        )
     }
+}
 
-    // println!("\n\n");
-    println!("Final tokens:\n");
-
-    let mut counter = 0;
-    for token in tokens {
-        println!("Token {counter}: {token:?}");
-        counter += 1;
+/// Rejects bidirectional control characters anywhere in the source, comments
+/// and strings included, since the lexer skips comments without looking at them.
+pub fn validate_source(code: &str) -> Result<(), LexError> {
+    match code.char_indices().find(|(_, c)| BIDI_CONTROLS.contains(c)) {
+        Some((position, c)) => Err(LexError {
+            code: ErrorCode::BIDI_CONTROL,
+            message: format!(
+                "bidirectional control character U+{:04X} isn't allowed in source code",
+                c as u32
+            ),
+            position,
+        }),
+        None => Ok(()),
     }
+}
 
-    println!("Chuncks of code: {counter_chunks}");
+enum Mode {
+    Code,
+    /// Inside a string literal opened at the given byte.
+    String(usize),
+    /// Inside `${...}`, with the number of braces opened in it.
+    Interpolation(usize),
 }
 
-fn remove_comments(source_code: &str) -> String {
-    let mut code_without_comments = String::new();
+/// Tokenizer *WIP*
+///
+/// Streams the tokens of `code` with their byte spans, reading every character
+/// once with constant-time lookahead. Stops after the first error.
+pub struct Lexer<'a> {
+    code: &'a str,
+    /// Byte offset of `code` in its file, added to every span and error.
+    offset: usize,
+    position: usize,
+    modes: Vec<Mode>,
+    type_params_depth: usize,
+    /// Tokens already read, for constructs that produce several at once.
+    pending: VecDeque<SpannedToken>,
+    /// Keeps whitespace and comments attached to the tokens.
+    lossless: bool,
+    trivia: Vec<Trivia>,
+    finished: bool,
+    failed: bool,
+}
 
-    for line in source_code.lines() {
-        // Check for single-line comments (//) and ignore the rest of the line
-        if let Some(index) = line.find("//") {
-            let line_without_comment = &line[0..index];
-            code_without_comments.push_str(line_without_comment.trim());
-        } else {
-            let line_without_leading_whitespace = line.trim_start();
-            code_without_comments.push_str(line_without_leading_whitespace);
+impl<'a> Lexer<'a> {
+    /// Lexer for `code`, a slice starting `offset` bytes into its file.
+    pub fn new(code: &'a str, offset: usize) -> Self {
+        Self {
+            code,
+            offset,
+            position: 0,
+            modes: vec![Mode::Code],
+            type_params_depth: 0,
+            pending: VecDeque::new(),
+            lossless: false,
+            trivia: Vec::new(),
+            finished: false,
+            failed: false,
         }
+    }
 
-        // Add a newline character after each processed line
-        code_without_comments.push('\n');
+    /// Makes the lexer keep whitespace and comments as trivia of the token
+    /// after them, ending with an [`Token::EndOfFile`] holding whatever trails
+    /// the last token.
+    ///
+    /// Joining the full spans of the tokens gives back the source unchanged.
+    pub fn lossless(mut self) -> Self {
+        self.lossless = true;
+        self
     }
 
-    println!("{}", code_without_comments);
+    fn peek(&self) -> Option<char> {
+        self.code[self.position..].chars().next()
+    }
 
-    code_without_comments
-}
+    fn peek_second(&self) -> Option<char> {
+        self.code[self.position..].chars().nth(1)
+    }
 
-fn code_chunckenizer(code: String) -> Vec<String> {
-    let mut inside_string = false;
-    let mut chuncks = Vec::<String>::new();
-    let mut buffer = String::new();
+    fn bump(&mut self) -> Option<char> {
+        let c = self.peek()?;
+        self.position += c.len_utf8();
+        Some(c)
+    }
 
-    for c in code.chars() {
-        match c {
-            '"' => {
-                buffer.push('"');
-                inside_string = !inside_string
-            }
-            ';' if !inside_string => {
-                buffer.push(';');
-                chuncks.push(buffer.to_string());
-                buffer.clear();
-            }
-            _ => buffer.push(c),
+    fn eat(&mut self, expected: char) -> bool {
+        if self.peek() == Some(expected) {
+            self.position += expected.len_utf8();
+            true
+        } else {
+            false
         }
     }
 
-    if !buffer.is_empty() {
-        chuncks.push(buffer.to_string());
-        buffer.clear();
+    fn error(&self, code: ErrorCode, message: String, at: usize) -> LexError {
+        LexError {
+            code,
+            message,
+            position: self.offset + at,
+        }
     }
 
-    chuncks
-}
-
-/// Tokenizer *WIP*
-pub fn tokenize(code: String) -> Vec<Token> {
-    // println!("{}", code);
-
-    let mut tokens: Vec<Token> = Vec::new();
-
-    let mut buffer: String = String::new();
-
-    let mut interpolation_buffer = String::new();
-
-    let mut inside_string = false;
+    fn spanned(&mut self, token: Token, start: usize) -> SpannedToken {
+        SpannedToken {
+            token,
+            span: self.offset + start..self.offset + self.position,
+            trivia: std::mem::take(&mut self.trivia),
+        }
+    }
 
-    let mut inside_interpolation = false;
+    fn next_token(&mut self) -> Result<Option<SpannedToken>, LexError> {
+        if let Some(token) = self.pending.pop_front() {
+            return Ok(Some(token));
+        }
 
-    let mut counter = 0;
+        match self.modes.last() {
+            Some(Mode::String(start)) => self.string_token(*start).map(Some),
+            _ => self.code_token(),
+        }
+    }
 
-    let mut chars = code.chars().peekable();
+    fn skip_trivia(&mut self) {
+        loop {
+            let start = self.position;
 
-    while let Some(c) = chars.next() {
-        if inside_interpolation {
-            match c {
-                '}' => {
-                    inside_interpolation = !inside_interpolation;
-                    tokens.push(Token::Identifier(interpolation_buffer.clone()));
-                    interpolation_buffer.clear();
-                    tokens.push(Token::Delimiter(CloseInterpolation));
-                }
-                _ => {
-                    interpolation_buffer.push(c);
+            let kind = match self.peek() {
+                Some('\n') => {
+                    self.bump();
+                    TriviaKind::Newline
                 }
-            }
-        } else if inside_string {
-            match c {
-                '"' if !buffer.ends_with('\\') => {
-                    inside_string = !inside_string;
-                    tokens.push(Token::Delimiter(CloseString));
-                    if !buffer.is_empty() {
-                        tokens.push(Token::LiteralString(buffer.clone()));
-                        // println!("{buffer}");
+                Some(' ' | '\t' | '\r') => {
+                    while self.peek().is_some_and(|c| matches!(c, ' ' | '\t' | '\r')) {
+                        self.bump();
                     }
-                    buffer.clear();
-                }
-                '"' if buffer.ends_with('\\') => {
-                    buffer.pop();
-                    buffer.push('"');
+                    TriviaKind::Whitespace
                 }
-                '$' if check_next_char(&code, &counter) == '{' && !buffer.ends_with('\\') => {
-                    tokens.push(Token::LiteralString(buffer.clone()));
-                    tokens.push(Token::Delimiter(OpenInterpolation));
-                    buffer.clear();
-                    chars.next();
-                    counter += 1;
-                    inside_interpolation = !inside_interpolation;
+                // A UTF-8 byte order mark is only allowed at the very start.
+                Some('\u{FEFF}') if self.offset + self.position == 0 => {
+                    self.bump();
+                    TriviaKind::Whitespace
                 }
-                '\\' => {
-                    let next_char = check_next_char(&code, &counter);
-
-                    if buffer.ends_with('\\') {
-                        buffer.pop();
-                        buffer.push('\\');
-                    } else {
-                        if next_char == '\\' {
-                            buffer.push('\\');
-                        } else {
-                            match next_char {
-                                'n' => buffer.push('\n'),
-                                't' => buffer.push('\t'),
-                                _ => {}
-                            }
-                            chars.next();
-                            counter += 1;
-                        }
+                Some('/') if self.peek_second() == Some('/') => {
+                    while self.peek().is_some_and(|c| c != '\n') {
+                        self.bump();
                     }
+                    TriviaKind::Comment
                 }
-                '\n' | '\t' => {}
-                _ => buffer.push(c),
+                _ => break,
+            };
+
+            if self.lossless {
+                self.trivia.push(Trivia {
+                    kind,
+                    span: self.offset + start..self.offset + self.position,
+                });
             }
-        } else {
-            match c {
-                ' ' | '\n' | '\t' => {
-                    if !buffer.is_empty() {
-                        process_buffer(&mut tokens, &buffer);
-                        buffer.clear();
-                    }
-                }
-                '<' => {
-                    if !buffer.is_empty() {
-                        process_buffer(&mut tokens, &buffer);
-                        buffer.clear();
-                    }
-                    tokens.push(Token::Operator(LessThan))
-                }
-                '>' => {
-                    let mut is_type_arrow = false;
-                    let mut is_function_arrow = false;
-
-                    if !buffer.is_empty() && buffer == "-" {
-                        is_type_arrow = !is_type_arrow;
-                        tokens.push(Token::Delimiter(TypeArrow));
-                        buffer.clear();
-                    } else if !buffer.is_empty() && buffer == "=" {
-                        is_function_arrow = !is_function_arrow;
-                        tokens.push(Token::Delimiter(FunctionArrow));
-                        buffer.clear();
-                    } else if !buffer.is_empty() {
-                        process_buffer(&mut tokens, &buffer);
-                        buffer.clear();
-                    }
+        }
+    }
 
-                    if !is_type_arrow && !is_function_arrow {
-                        tokens.push(Token::Operator(GreaterThan))
-                    }
+    fn code_token(&mut self) -> Result<Option<SpannedToken>, LexError> {
+        self.skip_trivia();
+
+        let start = self.position;
+
+        let Some(c) = self.bump() else {
+            // Only the enclosing string of an interpolation can be left open here.
+            return match self.modes.iter().rev().find_map(|mode| match mode {
+                Mode::String(start) => Some(*start),
+                _ => None,
+            }) {
+                Some(string) => Err(self.error(
+                    ErrorCode::UNTERMINATED_STRING,
+                    String::from("unterminated string"),
+                    string,
+                )),
+                None if self.lossless && !self.finished => {
+                    self.finished = true;
+                    Ok(Some(self.spanned(Token::EndOfFile, start)))
                 }
-                '{' => {
-                    if !buffer.is_empty() {
-                        process_buffer(&mut tokens, &buffer);
-                        buffer.clear();
-                    }
-                    tokens.push(Token::Delimiter(OpenObject));
-                }
-                '}' => {
-                    if !buffer.is_empty() {
-                        process_buffer(&mut tokens, &buffer);
-                        buffer.clear();
-                    }
-                    tokens.push(Token::Delimiter(CloseObject));
+                None => Ok(None),
+            };
+        };
+
+        let token = match c {
+            '<' => {
+                // Type parameters are always attached to the name they belong
+                // to, or follow a '::' in expressions, like `first::<int>(xs)`.
+                let before = &self.code[..start];
+                let follows_name = before
+                    .chars()
+                    .next_back()
+                    .is_some_and(|c| c == '_' || c.is_xid_continue());
+
+                if (follows_name || before.ends_with("::"))
+                    && opens_type_params(&self.code[self.position..])
+                {
+                    self.type_params_depth += 1;
+                    Token::Delimiter(OpenTypeParams)
+                } else {
+                    Token::Operator(LessThan)
                 }
-                '(' => {
-                    if !buffer.is_empty() {
-                        process_buffer(&mut tokens, &buffer);
-                        buffer.clear();
-                    }
-                    tokens.push(Token::Delimiter(OpenSequence));
-                }
-                ')' => {
-                    if !buffer.is_empty() {
-                        process_buffer(&mut tokens, &buffer);
-                        buffer.clear();
-                    }
-                    tokens.push(Token::Delimiter(CloseSequence));
-                }
-                '[' => {
-                    if !buffer.is_empty() {
-                        process_buffer(&mut tokens, &buffer);
-                        buffer.clear();
-                    }
-                    tokens.push(Token::Delimiter(OpenArray));
-                }
-                ']' => {
-                    if !buffer.is_empty() {
-                        process_buffer(&mut tokens, &buffer);
-                        buffer.clear();
-                    }
-                    tokens.push(Token::Delimiter(CloseArray));
-                }
-                '=' => {
-                    if !buffer.is_empty() {
-                        process_buffer(&mut tokens, &buffer);
-                        buffer.clear();
-                    }
-                    if check_next_char(&code, &counter) == '>' {
-                        buffer.push('=');
-                    } else if check_next_char(&code, &counter) == '=' {
-                        tokens.push(Token::Operator(Equals));
-                    } else if let Token::Operator(Equals) = tokens.last().unwrap() {
-                    } else {
-                        tokens.push(Token::Operator(Assign));
-                    }
+            }
+            '>' if self.type_params_depth > 0 => {
+                self.type_params_depth -= 1;
+                Token::Delimiter(CloseTypeParams)
+            }
+            '>' => Token::Operator(GreaterThan),
+            '-' if self.eat('>') => Token::Delimiter(TypeArrow),
+            '-' => Token::Operator(Subtract),
+            '=' if self.eat('>') => Token::Delimiter(FunctionArrow),
+            '=' if self.eat('=') => Token::Operator(Equals),
+            '=' => Token::Operator(Assign),
+            '+' => Token::Operator(Add),
+            '*' => Token::Operator(Multiply),
+            '/' => Token::Operator(Divide),
+            '?' => Token::Operator(Propagate),
+            '{' => {
+                if let Some(Mode::Interpolation(braces)) = self.modes.last_mut() {
+                    *braces += 1;
                 }
-                '+' => {
-                    if !buffer.is_empty() {
-                        process_buffer(&mut tokens, &buffer);
-                        buffer.clear();
-                    }
-                    tokens.push(Token::Operator(Add));
+                Token::Delimiter(OpenObject)
+            }
+            '}' => match self.modes.last_mut() {
+                Some(Mode::Interpolation(0)) => {
+                    self.modes.pop();
+                    Token::Delimiter(CloseInterpolation)
                 }
-                '-' => {
-                    if !buffer.is_empty() {
-                        process_buffer(&mut tokens, &buffer);
-                        buffer.clear();
-                    }
-                    if check_next_char(&code, &counter) == '>' {
-                        buffer.push('-');
-                    } else {
-                        tokens.push(Token::Operator(Subtract));
-                    }
+                Some(Mode::Interpolation(braces)) => {
+                    *braces -= 1;
+                    Token::Delimiter(CloseObject)
                 }
-                '*' => {
-                    if !buffer.is_empty() {
-                        process_buffer(&mut tokens, &buffer);
-                        buffer.clear();
-                    }
-                    tokens.push(Token::Operator(Multiply));
+                _ => Token::Delimiter(CloseObject),
+            },
+            '(' => Token::Delimiter(OpenSequence),
+            ')' => Token::Delimiter(CloseSequence),
+            '[' => Token::Delimiter(OpenArray),
+            ']' => Token::Delimiter(CloseArray),
+            ';' => Token::Delimiter(Semicolon),
+            ',' => Token::Delimiter(Comma),
+            ':' if self.eat(':') => Token::Delimiter(ImportSeparator),
+            ':' => Token::Delimiter(Colon),
+            '.' => Token::Delimiter(ObjectSeparator),
+            '"' => {
+                self.modes.push(Mode::String(start));
+                Token::Delimiter(OpenString)
+            }
+            'r' if self.code[self.position..]
+                .trim_start_matches('#')
+                .starts_with('"') =>
+            {
+                return self.raw_string(start).map(Some);
+            }
+            _ if c.is_ascii_digit() => return self.number(start).map(Some),
+            _ if c == '_' || c.is_xid_start() => return Ok(Some(self.word(start)?)),
+            _ => {
+                return Err(self.error(
+                    ErrorCode::UNEXPECTED_CHARACTER,
+                    format!("unexpected character '{}'", c.escape_debug()),
+                    start,
+                ))
+            }
+        };
+
+        Ok(Some(self.spanned(token, start)))
+    }
+
+    /// Reads a keyword, type name or identifier.
+    fn word(&mut self, start: usize) -> Result<SpannedToken, LexError> {
+        let code = self.code;
+
+        while self.peek().is_some_and(|c| c.is_xid_continue()) {
+            self.bump();
+        }
+
+        let word = &code[start..self.position];
+
+        let token = match keyword(word) {
+            Some(token) => token,
+            None if RESERVED_WORDS.contains(&word) => {
+                return Err(self.error(
+                    ErrorCode::RESERVED_WORD,
+                    format!("'{word}' is a reserved word and can't be used as an identifier"),
+                    start,
+                ));
+            }
+            None => Token::Identifier(Symbol::intern(&normalize_identifier(word))),
+        };
+
+        Ok(self.spanned(token, start))
+    }
+
+    fn number(&mut self, start: usize) -> Result<SpannedToken, LexError> {
+        let code = self.code;
+
+        loop {
+            let literal = &code[start..self.position];
+
+            match self.peek() {
+                Some(c) if c.is_alphanumeric() || c == '_' => {}
+                Some('.')
+                    if is_decimal_integer(literal)
+                        && self.peek_second().is_some_and(|c| c.is_ascii_digit()) => {}
+                Some('+' | '-') if is_exponent_prefix(literal) => {}
+                _ => break,
+            }
+
+            self.bump();
+        }
+
+        let literal = &code[start..self.position];
+
+        // `1.5` is a float, `1.to_str()` calls a method on an integer.
+        if is_decimal_integer(literal)
+            && self.peek() == Some('.')
+            && !self
+                .peek_second()
+                .is_some_and(|c| c == '_' || c.is_xid_start())
+        {
+            return Err(self.error(
+                ErrorCode::INVALID_NUMBER,
+                format!("'{literal}.' needs a digit after the '.', like '{literal}.0'"),
+                self.position,
+            ));
+        }
+
+        let token =
+            parse_number(literal).map_err(|(code, message)| self.error(code, message, start))?;
+
+        Ok(self.spanned(token, start))
+    }
+
+    /// Reads a raw string, `r"..."` or `r#"..."#`, without escapes or interpolation.
+    fn raw_string(&mut self, start: usize) -> Result<SpannedToken, LexError> {
+        let code = self.code;
+
+        let hashes = code[self.position..]
+            .chars()
+            .take_while(|c| *c == '#')
+            .count();
+        self.position += hashes + 1;
+
+        let open = self.spanned(Token::Delimiter(OpenString), start);
+
+        let terminator = format!("\"{}", "#".repeat(hashes));
+        let Some(length) = code[self.position..].find(&terminator) else {
+            return Err(self.error(
+                ErrorCode::UNTERMINATED_STRING,
+                String::from("unterminated raw string"),
+                start,
+            ));
+        };
+
+        let content = self.position;
+        self.position += length;
+
+        if length > 0 {
+            let raw = Token::LiteralString(Symbol::intern(&code[content..self.position]));
+            let raw = self.spanned(raw, content);
+            self.pending.push_back(raw);
+        }
+
+        let close = self.position;
+        self.position += terminator.len();
+        let close = self.spanned(Token::Delimiter(CloseString), close);
+        self.pending.push_back(close);
+
+        Ok(open)
+    }
+
+    fn string_token(&mut self, string: usize) -> Result<SpannedToken, LexError> {
+        let start = self.position;
+
+        let token = match self.peek() {
+            None => {
+                return Err(self.error(
+                    ErrorCode::UNTERMINATED_STRING,
+                    String::from("unterminated string"),
+                    string,
+                ))
+            }
+            Some('"') => {
+                self.bump();
+                self.modes.pop();
+                Token::Delimiter(CloseString)
+            }
+            Some('$') if self.peek_second() == Some('{') => {
+                self.position += 2;
+                self.modes.push(Mode::Interpolation(0));
+                Token::Delimiter(OpenInterpolation)
+            }
+            Some(_) => Token::LiteralString(Symbol::intern(&self.string_segment()?)),
+        };
+
+        Ok(self.spanned(token, start))
+    }
+
+    /// Reads string contents up to the closing '"' or the next interpolation.
+    ///
+    /// They're only copied when they contain escapes.
+    fn string_segment(&mut self) -> Result<Cow<'a, str>, LexError> {
+        let code = self.code;
+        let start = self.position;
+        let mut unescaped: Option<String> = None;
+
+        loop {
+            let at = self.position;
+
+            match self.peek() {
+                None | Some('"') => break,
+                Some('$') if self.peek_second() == Some('{') => break,
+                Some('\\') => {
+                    let text = unescaped.get_or_insert_with(|| code[start..at].to_string());
+                    self.bump();
+                    text.push(self.read_escape(at)?);
                 }
-                '/' => {
-                    if !buffer.is_empty() {
-                        process_buffer(&mut tokens, &buffer);
-                        buffer.clear();
+                Some(c) => {
+                    self.bump();
+                    if let Some(text) = unescaped.as_mut() {
+                        text.push(c);
                     }
-                    tokens.push(Token::Operator(Divide));
                 }
-                '.' => {
-                    let mut first_check = false;
-                    let mut is_nan = false;
-
-                    match buffer.parse::<i64>() {
-                        Ok(_) => {
-                            first_check = !first_check;
-                        }
-                        _ => {}
-                    }
+            }
+        }
 
-                    if !check_next_char(&code, &counter).is_alphabetic() && first_check {
-                        is_nan = !is_nan;
-                    }
+        Ok(match unescaped {
+            Some(text) => Cow::Owned(text),
+            None => Cow::Borrowed(&code[start..self.position]),
+        })
+    }
 
-                    if !buffer.is_empty() && !is_nan {
-                        process_buffer(&mut tokens, &buffer);
-                        buffer.clear();
-                    }
+    /// Reads the escape sequence following the '\\' at `backslash`.
+    ///
+    /// Supports `\n`, `\t`, `\r`, `\0`, `\\`, `\"`, `\$`, `\xNN` (ASCII only) and
+    /// `\u{...}` with up to six hex digits.
+    fn read_escape(&mut self, backslash: usize) -> Result<char, LexError> {
+        let Some(c) = self.bump() else {
+            return Err(self.error(
+                ErrorCode::INVALID_ESCAPE,
+                String::from("unterminated escape sequence"),
+                backslash,
+            ));
+        };
 
-                    if !is_nan {
-                        tokens.push(Token::Delimiter(ObjectSeparator));
-                    } else {
-                        buffer.push(c);
-                    }
+        match c {
+            'n' => Ok('\n'),
+            't' => Ok('\t'),
+            'r' => Ok('\r'),
+            '0' => Ok('\0'),
+            '\\' => Ok('\\'),
+            '"' => Ok('"'),
+            '$' => Ok('$'),
+            'x' => {
+                let digits = self.hex_digits(2);
+
+                if digits.len() != 2 {
+                    return Err(self.error(
+                        ErrorCode::INVALID_ESCAPE,
+                        String::from("'\\x' escape needs exactly two hex digits"),
+                        backslash,
+                    ));
                 }
-                ':' => {
-                    if !buffer.is_empty() {
-                        process_buffer(&mut tokens, &buffer);
-                        buffer.clear();
-                    }
 
-                    if check_next_char(&code, &counter) == ':' {
-                        buffer.push(':');
-                        buffer.push(':');
-                    }
+                match u8::from_str_radix(digits, 16) {
+                    Ok(value) if value.is_ascii() => Ok(value as char),
+                    _ => Err(self.error(
+                        ErrorCode::INVALID_ESCAPE,
+                        format!(
+                            "'\\x{digits}' is out of range, use '\\u{{...}}' for non-ASCII characters"
+                        ),
+                        backslash,
+                    )),
                 }
-                ';' => {
-                    if !buffer.is_empty() {
-                        process_buffer(&mut tokens, &buffer);
-                        buffer.clear();
-                    }
-                    tokens.push(Token::Delimiter(Semicolon));
-                }
-                '"' => {
-                    tokens.push(Token::Delimiter(OpenString));
-                    inside_string = !inside_string;
-                }
-                ',' => {
-                    if !buffer.is_empty() {
-                        process_buffer(&mut tokens, &buffer);
-                        buffer.clear();
-                    }
-                    tokens.push(Token::Delimiter(Comma));
+            }
+            'u' => {
+                if !self.eat('{') {
+                    return Err(self.error(
+                        ErrorCode::INVALID_ESCAPE,
+                        String::from("expected '{' after '\\u'"),
+                        backslash,
+                    ));
                 }
-                _ => {
-                    buffer.push(c);
+
+                let digits = self.hex_digits(6);
+
+                if !self.eat('}') {
+                    return Err(self.error(
+                        ErrorCode::INVALID_ESCAPE,
+                        String::from("unterminated '\\u{...}' escape"),
+                        backslash,
+                    ));
                 }
+
+                u32::from_str_radix(digits, 16)
+                    .ok()
+                    .and_then(char::from_u32)
+                    .ok_or_else(|| {
+                        self.error(
+                            ErrorCode::INVALID_ESCAPE,
+                            format!("'\\u{{{digits}}}' is not a valid character"),
+                            backslash,
+                        )
+                    })
             }
+            _ => Err(self.error(
+                ErrorCode::INVALID_ESCAPE,
+                format!("unknown escape sequence '\\{c}'"),
+                backslash,
+            )),
         }
-
-        counter += 1;
     }
 
-    if !buffer.is_empty() {
-        process_buffer(&mut tokens, &buffer);
-    }
+    fn hex_digits(&mut self, max: usize) -> &'a str {
+        let code = self.code;
+        let start = self.position;
 
-    let mut counter = 0;
+        while self.position - start < max && self.peek().is_some_and(|c| c.is_ascii_hexdigit()) {
+            self.bump();
+        }
 
-    for token in &tokens {
-        counter += 1;
-        // println!("Token {}: {:?}", counter, token);
+        &code[start..self.position]
     }
+}
 
-    // println!("{tokens:?}");
+impl<'a> Iterator for Lexer<'a> {
+    type Item = Result<SpannedToken, LexError>;
 
-    tokens
-}
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.failed {
+            return None;
+        }
 
-fn check_next_char(code: &str, counter: &usize) -> char {
-    code.chars().nth(*counter + 1).unwrap()
+        let token = self.next_token().transpose();
+        self.failed = matches!(token, Some(Err(_)));
+        token
+    }
 }
 
-fn process_buffer(tokens: &mut Vec<Token>, buffer: &str) {
-    match buffer {
-        "::" => {
-            tokens.push(Token::Delimiter(ImportSeparator));
-        }
-        "import" => {
-            tokens.push(Token::Keyword(Import));
-        }
-        "std" => {
-            tokens.push(Token::Keyword(StandardLibrary));
-        }
-        "main" => {
-            tokens.push(Token::Keyword(Main));
-        }
-        "==" => {
-            tokens.push(Token::Operator(Equals));
-        }
-        "=>" => {
-            tokens.push(Token::Delimiter(FunctionArrow));
-        }
-        "->" => {
-            tokens.push(Token::Delimiter(TypeArrow));
-        }
-        "fn" => {
-            tokens.push(Token::Keyword(Function));
-        }
-        "type" => {
-            tokens.push(Token::Keyword(Type));
-        }
-        "const" => {
-            tokens.push(Token::Keyword(Const));
-        }
-        "let" => {
-            tokens.push(Token::Keyword(Let));
-        }
-        "int" => {
-            tokens.push(Token::Types(Int));
+/// Looks ahead from a '<' to tell a type parameter list from a comparison.
+///
+/// It is a type parameter list when a matching '>' is found before anything
+/// that can't appear inside one, and every name in it is a type: a built-in
+/// type or a capitalized name, like `first<T>`, `Map<str, [int]>` or
+/// `Box<utils::Text>`. Names before a '::' are modules and aren't checked.
+///
+/// Values are lowercase by convention, so `a<b`, `if x<y {` and
+/// `f(a<b, c>d)` stay comparisons. Comparing capitalized values needs spaces,
+/// `f(A < B, C > D)`, as `f(A<B, C>D)` reads as a type parameter list.
+fn opens_type_params(rest: &str) -> bool {
+    let mut depth = 1;
+    let mut word = None;
+
+    for (index, c) in rest.char_indices() {
+        if c == '_' || c.is_alphanumeric() {
+            word.get_or_insert(index);
+            continue;
         }
-        "float" => {
-            tokens.push(Token::Types(Float));
-        }
-        "null" => {
-            tokens.push(Token::Types(Null));
-        }
-        "str" => {
-            tokens.push(Token::Types(StringType));
-        }
-        "bool" => {
-            tokens.push(Token::Types(Bool));
-        }
-        "print" => {
-            tokens.push(Token::Keyword(Print));
-        }
-        "println" => {
-            tokens.push(Token::Keyword(PrintLine));
+
+        if let Some(start) = word.take() {
+            if c != ':' && !is_type_name(&rest[start..index]) {
+                return false;
+            }
         }
-        "new" => {
-            tokens.push(Token::Keyword(New));
+
+        match c {
+            '<' => depth += 1,
+            '>' => {
+                depth -= 1;
+                if depth == 0 {
+                    return true;
+                }
+            }
+            '[' | ']' | ',' | ':' | ' ' => {}
+            _ => return false,
         }
-        "if" => {
-            tokens.push(Token::Keyword(If));
+    }
+
+    false
+}
+
+/// Whether `word` names a type, a built-in one or a capitalized name.
+fn is_type_name(word: &str) -> bool {
+    word.starts_with(char::is_uppercase) || matches!(keyword(word), Some(Token::Types(_)))
+}
+
+/// Token for a keyword, type name or literal word.
+fn keyword(word: &str) -> Option<Token> {
+    let token = match word {
+        "import" => Token::Keyword(Import),
+        "std" => Token::Keyword(StandardLibrary),
+        "main" => Token::Keyword(Main),
+        "fn" => Token::Keyword(Function),
+        "type" => Token::Keyword(Type),
+        "const" => Token::Keyword(Const),
+        "let" => Token::Keyword(Let),
+        "int" => Token::Types(Int),
+        "float" => Token::Types(Float),
+        "null" => Token::Types(Null),
+        "str" => Token::Types(StringType),
+        "bool" => Token::Types(Bool),
+        "uint" => Token::Types(UInt),
+        "array" => Token::Types(Array),
+        "true" => Token::LiteralBool(true),
+        "false" => Token::LiteralBool(false),
+        "new" => Token::Keyword(New),
+        "interface" => Token::Keyword(Interface),
+        "impl" => Token::Keyword(Implement),
+        "self" => Token::Keyword(SelfValue),
+        "return" => Token::Keyword(Return),
+        "break" => Token::Keyword(Break),
+        "continue" => Token::Keyword(Continue),
+        "in" => Token::Keyword(In),
+        "as" => Token::Keyword(As),
+        "match" => Token::Keyword(Match),
+        "pub" => Token::Keyword(Public),
+        "mut" => Token::Keyword(Mutable),
+        "if" => Token::Keyword(If),
+        "else" => Token::Keyword(Else),
+        "loop" => Token::Keyword(Loop),
+        "while" => Token::Keyword(While),
+        "for" => Token::Keyword(For),
+        _ => return None,
+    };
+
+    Some(token)
+}
+
+/// Returns an identifier in NFC, so `ação` written with combining marks is
+/// the same name. The lexer already checked it against UAX #31.
+///
+/// Names that can be confused with others are warned about when the module
+/// is loaded.
+fn normalize_identifier(word: &str) -> Cow<'_, str> {
+    if is_nfc(word) {
+        Cow::Borrowed(word)
+    } else {
+        Cow::Owned(word.nfc().collect())
+    }
+}
+
+/// Generates a few megabytes of code and measures how fast the lexer reads it.
+#[cfg(feature = "devkit")]
+pub fn bench() {
+    use std::time::Instant;
+
+    let function = r#"
+        // Adds up the values of an array.
+        fn sum_NUMBER(xs: [int], scale: float) -> float {
+            let total = 0;
+            for x in xs { if x < 10 { total = total + x * 2; } }
+            println("total: ${total}, scale: ${scale}\n");
+            total * scale + 1.5e3 + 0xFF
         }
-        "else" => tokens.push(Token::Keyword(Else)),
-        "loop" => {
-            tokens.push(Token::Keyword(Loop));
+    "#;
+
+    let code: String = (0..40_000)
+        .map(|number| function.replace("NUMBER", &number.to_string()))
+        .collect();
+
+    let start = Instant::now();
+    let tokens = Lexer::new(&code, 0).collect::<Result<Vec<_>, _>>().unwrap();
+    let elapsed = start.elapsed();
+
+    let megabytes = code.len() as f64 / (1024.0 * 1024.0);
+
+    println!(
+        "Lexer benchmark: {megabytes:.1} MiB, {} tokens",
+        tokens.len()
+    );
+    println!("Time: {elapsed:?}");
+    println!("Throughput: {:.1} MiB/s", megabytes / elapsed.as_secs_f64());
+}
+
+/// Parses a numeric literal.
+///
+/// Supports `0x`/`0o`/`0b` prefixes, `_` digit separators, exponents and the
+/// `u`, `i` and `f` suffixes, like `0xFF`, `1_000_000`, `1.5e10`, `10u` or `1.0f`.
+/// Literals without a suffix are an int, or a uint when they don't fit in one.
+fn parse_number(literal: &str) -> Result<Token, (ErrorCode, String)> {
+    let digits = literal.replace('_', "");
+
+    let (radix, body) = match digits.get(..2) {
+        Some("0x" | "0X") => (16, &digits[2..]),
+        Some("0o" | "0O") => (8, &digits[2..]),
+        Some("0b" | "0B") => (2, &digits[2..]),
+        _ => (10, &digits[..]),
+    };
+
+    // 'f' is a hex digit, so only decimal literals can have the float suffix.
+    let (body, suffix) = match body.chars().last() {
+        Some(c @ ('u' | 'i')) => (&body[..body.len() - 1], Some(c)),
+        Some('f') if radix == 10 => (&body[..body.len() - 1], Some('f')),
+        _ => (body, None),
+    };
+
+    if body.is_empty() {
+        return Err((
+            ErrorCode::INVALID_NUMBER,
+            format!("numeric literal '{literal}' has no digits"),
+        ));
+    }
+
+    if radix == 10 && (suffix == Some('f') || body.contains(['.', 'e', 'E'])) {
+        if suffix.is_some_and(|suffix| suffix != 'f') {
+            return Err((
+                ErrorCode::INVALID_NUMBER,
+                format!("float literal '{literal}' can't have an integer suffix"),
+            ));
         }
-        "while" => {
-            tokens.push(Token::Keyword(While));
+
+        return match body.parse::<f64>() {
+            Ok(value) if value.is_finite() => Ok(Token::LiteralFloat(value)),
+            Ok(_) => Err((
+                ErrorCode::NUMBER_OUT_OF_RANGE,
+                format!("float literal '{literal}' is out of range"),
+            )),
+            Err(_) => Err((
+                ErrorCode::INVALID_NUMBER,
+                format!("invalid float literal '{literal}'"),
+            )),
+        };
+    }
+
+    let value = u64::from_str_radix(body, radix).map_err(|err| match err.kind() {
+        IntErrorKind::PosOverflow => (
+            ErrorCode::NUMBER_OUT_OF_RANGE,
+            format!("literal '{literal}' is out of range for uint"),
+        ),
+        _ => (
+            ErrorCode::INVALID_NUMBER,
+            format!("invalid numeric literal '{literal}'"),
+        ),
+    })?;
+
+    match suffix {
+        Some('u') => Ok(Token::LiteralUInteger(value)),
+        Some(_) => i64::try_from(value)
+            .map(Token::LiteralInteger)
+            .map_err(|_| {
+                (
+                    ErrorCode::NUMBER_OUT_OF_RANGE,
+                    format!("literal '{literal}' is out of range for int"),
+                )
+            }),
+        None => {
+            Ok(i64::try_from(value).map_or(Token::LiteralUInteger(value), Token::LiteralInteger))
         }
-        "for" => {
-            tokens.push(Token::Keyword(For));
+    }
+}
+
+/// Checks if the buffer holds a decimal integer so far, like `42` or `1_000`.
+fn is_decimal_integer(buffer: &str) -> bool {
+    buffer.starts_with(|c: char| c.is_ascii_digit())
+        && buffer.chars().all(|c| c.is_ascii_digit() || c == '_')
+}
+
+/// Checks if the buffer is a decimal literal waiting for its exponent sign, like `1.5e`.
+fn is_exponent_prefix(buffer: &str) -> bool {
+    let Some(mantissa) = buffer.strip_suffix(['e', 'E']) else {
+        return false;
+    };
+
+    mantissa.starts_with(|c: char| c.is_ascii_digit())
+        && mantissa
+            .chars()
+            .all(|c| c.is_ascii_digit() || c == '_' || c == '.')
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Tokens of `code` with their spans, panicking on errors.
+    fn lex(code: &str) -> Vec<(Token, Range<usize>)> {
+        Lexer::new(code, 0)
+            .map(|token| token.map(|t| (t.token, t.span)).unwrap())
+            .collect()
+    }
+
+    /// The error that stops the lexer on `code`.
+    fn lex_error(code: &str) -> LexError {
+        Lexer::new(code, 0)
+            .find_map(Result::err)
+            .unwrap_or_else(|| panic!("{code:?} lexed without errors"))
+    }
+
+    fn string(text: &str) -> Token {
+        Token::LiteralString(Symbol::intern(text))
+    }
+
+    fn identifier(name: &str) -> Token {
+        Token::Identifier(Symbol::intern(name))
+    }
+
+    #[test]
+    fn tokens_display_as_written() {
+        let dump: Vec<String> = lex("fn f() -> float { g::h(\"a\\n${x}\", 2.0, 3u)? }")
+            .into_iter()
+            .map(|(token, _)| token.to_string())
+            .collect();
+
+        assert_eq!(
+            dump,
+            [
+                "keyword fn",
+                "identifier f",
+                "delimiter (",
+                "delimiter )",
+                "delimiter ->",
+                "type float",
+                "delimiter {",
+                "identifier g",
+                "delimiter ::",
+                "identifier h",
+                "delimiter (",
+                "delimiter \"",
+                "string \"a\\n\"",
+                "delimiter ${",
+                "identifier x",
+                "delimiter }",
+                "delimiter \"",
+                "delimiter ,",
+                "float 2.0",
+                "delimiter ,",
+                "uint 3",
+                "delimiter )",
+                "operator ?",
+                "delimiter }",
+            ]
+        );
+    }
+
+    #[test]
+    fn spans_are_byte_offsets() {
+        assert_eq!(
+            lex("let ação = 1;"),
+            vec![
+                (Token::Keyword(Let), 0..3),
+                (identifier("ação"), 4..10),
+                (Token::Operator(Assign), 11..12),
+                (Token::LiteralInteger(1), 13..14),
+                (Token::Delimiter(Semicolon), 14..15),
+            ]
+        );
+    }
+
+    #[test]
+    fn spans_start_at_the_offset() {
+        let spans: Vec<_> = Lexer::new("a -> b", 100)
+            .map(|token| token.unwrap().span)
+            .collect();
+
+        assert_eq!(spans, vec![100..101, 102..104, 105..106]);
+        assert_eq!(
+            Lexer::new("  $", 10).next().unwrap().unwrap_err().position,
+            12
+        );
+    }
+
+    #[test]
+    fn two_character_tokens() {
+        let tokens: Vec<_> = lex("a::b -> => == = :").into_iter().map(|t| t.0).collect();
+
+        assert_eq!(
+            tokens,
+            vec![
+                identifier("a"),
+                Token::Delimiter(ImportSeparator),
+                identifier("b"),
+                Token::Delimiter(TypeArrow),
+                Token::Delimiter(FunctionArrow),
+                Token::Operator(Equals),
+                Token::Operator(Assign),
+                Token::Delimiter(Colon),
+            ]
+        );
+    }
+
+    /// Kinds of the '<' and '>' tokens of `code`.
+    fn angle_brackets(code: &str) -> Vec<Token> {
+        lex(code)
+            .into_iter()
+            .map(|t| t.0)
+            .filter(|token| {
+                matches!(
+                    token,
+                    Token::Operator(LessThan | GreaterThan)
+                        | Token::Delimiter(OpenTypeParams | CloseTypeParams)
+                )
+            })
+            .collect()
+    }
+
+    #[test]
+    fn type_parameters() {
+        let params = vec![
+            Token::Delimiter(OpenTypeParams),
+            Token::Delimiter(CloseTypeParams),
+        ];
+
+        for code in [
+            "fn first<T>(xs: [T]) -> T",
+            "let Map<str, [int]> m",
+            "let Box<utils::Text> b",
+            "first::<int>(xs)",
+            "type Result<T> { Ok(T) }",
+        ] {
+            assert_eq!(angle_brackets(code), params, "{code}");
         }
-        "+" => {
-            tokens.push(Token::Operator(Add));
+
+        assert_eq!(
+            angle_brackets("let Result<Map<str, T>> r"),
+            vec![
+                Token::Delimiter(OpenTypeParams),
+                Token::Delimiter(OpenTypeParams),
+                Token::Delimiter(CloseTypeParams),
+                Token::Delimiter(CloseTypeParams),
+            ]
+        );
+    }
+
+    #[test]
+    fn comparisons() {
+        let comparisons = vec![Token::Operator(LessThan), Token::Operator(GreaterThan)];
+
+        for code in [
+            "f(a<b, c>d)",
+            "if x<y { y>3 }",
+            "a < T > b",
+            "f(A < B, C > D)",
+            "f(a<T, c>d)",
+        ] {
+            assert_eq!(angle_brackets(code), comparisons, "{code}");
         }
-        "-" => {
-            tokens.push(Token::Operator(Subtract));
+
+        assert_eq!(angle_brackets("a<b"), vec![Token::Operator(LessThan)]);
+    }
+
+    #[test]
+    fn escapes() {
+        assert_eq!(
+            lex(r#""e\x41\u{1F600}\\\n\t\r\0\"\$""#),
+            vec![
+                (Token::Delimiter(OpenString), 0..1),
+                (string("eA\u{1F600}\\\n\t\r\0\"$"), 1..29),
+                (Token::Delimiter(CloseString), 29..30),
+            ]
+        );
+    }
+
+    #[test]
+    fn invalid_escapes_point_at_the_backslash() {
+        for (code, message) in [
+            (r#""ab\q""#, "unknown escape sequence '\\q'"),
+            (r#""ab\x4""#, "'\\x' escape needs exactly two hex digits"),
+            (
+                r#""ab\xE9""#,
+                "'\\xE9' is out of range, use '\\u{...}' for non-ASCII characters",
+            ),
+            (r#""ab\u41""#, "expected '{' after '\\u'"),
+            (r#""ab\u{41""#, "unterminated '\\u{...}' escape"),
+            (r#""ab\u{D800}""#, "'\\u{D800}' is not a valid character"),
+        ] {
+            let error = lex_error(code);
+
+            assert_eq!(error.code, ErrorCode::INVALID_ESCAPE, "{code}");
+            assert_eq!(error.message, message, "{code}");
+            assert_eq!(error.position, 3, "{code}");
         }
-        "*" => {
-            tokens.push(Token::Operator(Multiply));
+    }
+
+    #[test]
+    fn raw_strings_keep_quotes_and_backslashes() {
+        assert_eq!(
+            lex(r##"r#"q "z" \n"# r"""##),
+            vec![
+                (Token::Delimiter(OpenString), 0..3),
+                (string(r#"q "z" \n"#), 3..11),
+                (Token::Delimiter(CloseString), 11..13),
+                (Token::Delimiter(OpenString), 14..16),
+                (Token::Delimiter(CloseString), 16..17),
+            ]
+        );
+    }
+
+    #[test]
+    fn nested_interpolation() {
+        let tokens: Vec<_> = lex(r#""a ${ "b ${x}" } c ${ {} }""#)
+            .into_iter()
+            .map(|t| t.0)
+            .collect();
+
+        assert_eq!(
+            tokens,
+            vec![
+                Token::Delimiter(OpenString),
+                string("a "),
+                Token::Delimiter(OpenInterpolation),
+                Token::Delimiter(OpenString),
+                string("b "),
+                Token::Delimiter(OpenInterpolation),
+                identifier("x"),
+                Token::Delimiter(CloseInterpolation),
+                Token::Delimiter(CloseString),
+                Token::Delimiter(CloseInterpolation),
+                string(" c "),
+                Token::Delimiter(OpenInterpolation),
+                Token::Delimiter(OpenObject),
+                Token::Delimiter(CloseObject),
+                Token::Delimiter(CloseInterpolation),
+                Token::Delimiter(CloseString),
+            ]
+        );
+    }
+
+    #[test]
+    fn byte_order_mark_only_at_the_start() {
+        assert_eq!(lex("\u{FEFF}let"), vec![(Token::Keyword(Let), 3..6)]);
+
+        let error = lex_error("let \u{FEFF}");
+        assert_eq!(error.code, ErrorCode::UNEXPECTED_CHARACTER);
+        assert_eq!(error.position, 4);
+
+        // In a slice further into the file it's not the start anymore.
+        assert!(Lexer::new("\u{FEFF}", 5).next().unwrap().is_err());
+    }
+
+    #[test]
+    fn unterminated_strings_point_at_their_start() {
+        for (code, position) in [
+            ("x = \"abc", 4),
+            ("x = \"a ${y", 4),
+            ("x = \"a ${ \"b", 10),
+            ("x = r#\"abc\"", 4),
+        ] {
+            let error = lex_error(code);
+
+            assert_eq!(error.code, ErrorCode::UNTERMINATED_STRING, "{code}");
+            assert_eq!(error.position, position, "{code}");
         }
-        "/" => {
-            tokens.push(Token::Operator(Divide));
+    }
+
+    #[test]
+    fn errors_stop_the_lexer() {
+        let mut lexer = Lexer::new("a $ b", 0);
+
+        assert!(lexer.next().unwrap().is_ok());
+        assert_eq!(lexer.next().unwrap().unwrap_err().position, 2);
+        assert!(lexer.next().is_none());
+    }
+
+    #[test]
+    fn reserved_words_and_bidi_controls() {
+        let error = lex_error("let static");
+        assert_eq!(error.code, ErrorCode::RESERVED_WORD);
+        assert_eq!(error.position, 4);
+
+        let error = validate_source("// a \u{202E} b").unwrap_err();
+        assert_eq!(error.code, ErrorCode::BIDI_CONTROL);
+        assert_eq!(error.position, 5);
+    }
+
+    #[test]
+    fn numbers_with_prefixes_separators_and_exponents() {
+        for (literal, token) in [
+            ("0", Token::LiteralInteger(0)),
+            ("0xFF", Token::LiteralInteger(255)),
+            ("0x1f", Token::LiteralInteger(31)),
+            ("0o17", Token::LiteralInteger(15)),
+            ("0B1010", Token::LiteralInteger(10)),
+            ("1_000_000", Token::LiteralInteger(1_000_000)),
+            ("1.5", Token::LiteralFloat(1.5)),
+            ("1.5e3", Token::LiteralFloat(1500.0)),
+            ("2E-2", Token::LiteralFloat(0.02)),
+            ("9223372036854775807", Token::LiteralInteger(i64::MAX)),
+            ("9223372036854775808", Token::LiteralUInteger(1 << 63)),
+        ] {
+            assert_eq!(parse_number(literal), Ok(token), "{literal}");
         }
-        "=" => {
-            tokens.push(Token::Operator(Assign));
+    }
+
+    #[test]
+    fn number_suffixes() {
+        for (literal, token) in [
+            ("10u", Token::LiteralUInteger(10)),
+            ("0xFFu", Token::LiteralUInteger(255)),
+            ("10i", Token::LiteralInteger(10)),
+            ("3f", Token::LiteralFloat(3.0)),
+            ("1.0f", Token::LiteralFloat(1.0)),
+            ("18446744073709551615u", Token::LiteralUInteger(u64::MAX)),
+        ] {
+            assert_eq!(parse_number(literal), Ok(token), "{literal}");
         }
-        ";" => {
-            tokens.push(Token::Delimiter(Semicolon));
+    }
+
+    #[test]
+    fn invalid_numbers() {
+        for (literal, code, message) in [
+            (
+                "0x",
+                ErrorCode::INVALID_NUMBER,
+                "numeric literal '0x' has no digits",
+            ),
+            (
+                "0b2",
+                ErrorCode::INVALID_NUMBER,
+                "invalid numeric literal '0b2'",
+            ),
+            (
+                "1.5u",
+                ErrorCode::INVALID_NUMBER,
+                "float literal '1.5u' can't have an integer suffix",
+            ),
+            (
+                "1e999",
+                ErrorCode::NUMBER_OUT_OF_RANGE,
+                "float literal '1e999' is out of range",
+            ),
+            (
+                "18446744073709551616",
+                ErrorCode::NUMBER_OUT_OF_RANGE,
+                "literal '18446744073709551616' is out of range for uint",
+            ),
+            (
+                "9223372036854775808i",
+                ErrorCode::NUMBER_OUT_OF_RANGE,
+                "literal '9223372036854775808i' is out of range for int",
+            ),
+        ] {
+            assert_eq!(
+                parse_number(literal),
+                Err((code, String::from(message))),
+                "{literal}"
+            );
         }
-        _ => {
-            // Check if the buffer is a numeric literal
-            if let Ok(value) = buffer.parse::<i64>() {
-                tokens.push(Token::LiteralInteger(value));
-            } else if let Ok(value) = buffer.parse::<u64>() {
-                tokens.push(Token::LiteralUInteger(value));
-            } else if let Ok(value) = buffer.parse::<f64>() {
-                tokens.push(Token::LiteralFloat(value));
-            } else {
-                // Assume it's an identifier
-                tokens.push(Token::Identifier(buffer.to_string()));
+    }
+
+    #[test]
+    fn numbers_in_source() {
+        assert_eq!(
+            lex("1.5e-3 x.y 1.to_str"),
+            vec![
+                (Token::LiteralFloat(1.5e-3), 0..6),
+                (identifier("x"), 7..8),
+                (Token::Delimiter(ObjectSeparator), 8..9),
+                (identifier("y"), 9..10),
+                (Token::LiteralInteger(1), 11..12),
+                (Token::Delimiter(ObjectSeparator), 12..13),
+                (identifier("to_str"), 13..19),
+            ]
+        );
+
+        let error = lex_error("x = 12.;");
+        assert_eq!(error.code, ErrorCode::INVALID_NUMBER);
+        assert_eq!(error.position, 6);
+
+        assert_eq!(lex_error("x = 0x;").position, 4);
+    }
+
+    /// Random sources that lex give back the exact source from their
+    /// lossless tokens. Those that don't lex are skipped, the property only
+    /// holds for valid ones.
+    #[test]
+    fn lossless_tokens_rebuild_the_source() {
+        const CASES: usize = 20_000;
+
+        let fragments = [
+            "fn",
+            "let",
+            "x",
+            "ação",
+            "_y2",
+            " ",
+            "  ",
+            "\t",
+            "\n",
+            "\r\n",
+            "\n\n",
+            "// comment ; }\n",
+            "//",
+            "\"text\"",
+            "\"esc \\n \\\" \\u{48}\"",
+            "\"a ${x + 1} b\"",
+            "\"${ \"${y}\" }\"",
+            "r\"raw ; } //\"",
+            "r#\"a \"quote\"#",
+            "0",
+            "42",
+            "1.5e3",
+            "0xFF",
+            "10u",
+            "{",
+            "}",
+            "(",
+            ")",
+            "[",
+            "]",
+            ";",
+            ",",
+            ":",
+            "::",
+            ".",
+            "->",
+            "=>",
+            "==",
+            "=",
+            "<",
+            ">",
+            "+",
+            "-",
+            "*",
+            "/",
+            "?",
+        ];
+
+        // xorshift, reproducible without pulling in a crate for it.
+        let mut state: u64 = 0x2545_F491_4F6C_DD1D;
+        let mut random = move |bound: usize| {
+            state ^= state << 13;
+            state ^= state >> 7;
+            state ^= state << 17;
+            (state % bound as u64) as usize
+        };
+
+        let mut checked = 0;
+
+        for case in 0..CASES {
+            let mut code = String::new();
+
+            if random(10) == 0 {
+                code.push('\u{FEFF}');
+            }
+
+            for _ in 0..random(40) {
+                code.push_str(fragments[random(fragments.len())]);
             }
+
+            let Ok(tokens) = Lexer::new(&code, 0)
+                .lossless()
+                .collect::<Result<Vec<_>, _>>()
+            else {
+                continue;
+            };
+
+            let rebuilt: String = tokens.iter().map(|t| &code[t.full_span()]).collect();
+
+            assert_eq!(rebuilt, code, "case {case} isn't lossless");
+            assert_eq!(tokens.last().map(|t| &t.token), Some(&Token::EndOfFile));
+
+            checked += 1;
         }
+
+        // Most random sources don't lex, make sure enough of them did.
+        assert!(
+            checked > CASES / 10,
+            "only {checked} of {CASES} sources lexed"
+        );
     }
 }
@@ -0,0 +1,114 @@
+use crate::compiler_internals::modules::ModuleGraph;
+use crate::compiler_internals::tokenizer::{
+    Delimiter, Keywords, LexError, Lexer, SpannedToken, Token, TriviaKind,
+};
+
+/// Public item of a module, as shown in the documentation.
+struct Item {
+    /// Declaration up to its body or ';', like `pub fn len(text: str) -> uint`.
+    signature: String,
+    /// Its `///` comment lines, without the slashes.
+    docs: Vec<String>,
+}
+
+/// Writes Markdown documentation of the project modules of `graph`: the
+/// signature and `///` comments of each of their `pub` items.
+///
+/// Standard library modules are left out, like in packages.
+pub fn markdown(title: &str, graph: &ModuleGraph) -> Result<String, LexError> {
+    let mut markdown = format!("# {title}\n");
+
+    for module in graph
+        .modules
+        .iter()
+        .filter(|module| !module.name.starts_with("std::"))
+    {
+        markdown.push_str(&format!("\n## {}\n", module.name));
+
+        let items = public_items(&module.source.code)?;
+        if items.is_empty() {
+            markdown.push_str("\nNo public items.\n");
+        }
+
+        for item in items {
+            markdown.push_str(&format!("\n```squid\n{}\n```\n", item.signature));
+
+            if !item.docs.is_empty() {
+                markdown.push('\n');
+                for line in item.docs {
+                    markdown.push_str(&line);
+                    markdown.push('\n');
+                }
+            }
+        }
+    }
+
+    Ok(markdown)
+}
+
+/// Top-level `pub` items of `code`, in source order.
+fn public_items(code: &str) -> Result<Vec<Item>, LexError> {
+    let tokens = Lexer::new(code, 0)
+        .lossless()
+        .collect::<Result<Vec<SpannedToken>, _>>()?;
+
+    let mut items = Vec::new();
+    let mut depth = 0;
+
+    for (i, token) in tokens.iter().enumerate() {
+        match token.token {
+            Token::Delimiter(Delimiter::OpenObject) => depth += 1,
+            Token::Delimiter(Delimiter::CloseObject) => depth -= 1,
+            Token::Keyword(Keywords::Public) if depth == 0 => {
+                let end = tokens[i..]
+                    .iter()
+                    .find(|t| {
+                        matches!(
+                            t.token,
+                            Token::Delimiter(Delimiter::OpenObject | Delimiter::Semicolon)
+                                | Token::EndOfFile
+                        )
+                    })
+                    .map_or(code.len(), |t| t.span.start);
+
+                items.push(Item {
+                    signature: code[token.span.start..end].trim_end().to_string(),
+                    docs: doc_comment(code, token),
+                });
+            }
+            _ => {}
+        }
+    }
+
+    Ok(items)
+}
+
+/// The `///` comment lines right before `token`, with no blank line between
+/// them and it.
+fn doc_comment(code: &str, token: &SpannedToken) -> Vec<String> {
+    let mut docs = Vec::new();
+    let mut newlines = 0;
+
+    for trivia in token.trivia.iter().rev() {
+        match trivia.kind {
+            TriviaKind::Whitespace => {}
+            TriviaKind::Newline => {
+                newlines += 1;
+                if newlines > 1 {
+                    break;
+                }
+            }
+            TriviaKind::Comment => {
+                let Some(line) = code[trivia.span.clone()].strip_prefix("///") else {
+                    break;
+                };
+
+                docs.push(line.strip_prefix(' ').unwrap_or(line).to_string());
+                newlines = 0;
+            }
+        }
+    }
+
+    docs.reverse();
+    docs
+}
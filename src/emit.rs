@@ -0,0 +1,147 @@
+use crate::compiler_internals::modules::ModuleGraph;
+use crate::compiler_internals::Lexer;
+use crate::package;
+use clap::ValueEnum;
+use std::fmt::Write;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// Intermediate representation the compiler can dump with `--emit`.
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq)]
+pub enum Stage {
+    Tokens,
+    Ast,
+    TypedAst,
+    Ir,
+    Asm,
+    Bytecode,
+}
+
+/// Bytes shown on each line of a bytecode dump.
+const BYTES_PER_LINE: usize = 16;
+
+impl Stage {
+    /// Name used on the command line and in dump file names.
+    pub fn name(self) -> &'static str {
+        match self {
+            Stage::Tokens => "tokens",
+            Stage::Ast => "ast",
+            Stage::TypedAst => "typed-ast",
+            Stage::Ir => "ir",
+            Stage::Asm => "asm",
+            Stage::Bytecode => "bytecode",
+        }
+    }
+
+    /// Fails for stages of parts of the compiler that don't exist yet.
+    pub fn check_available(self) -> Result<(), String> {
+        let missing = match self {
+            Stage::Tokens | Stage::Bytecode => return Ok(()),
+            Stage::Ast | Stage::TypedAst => "parser",
+            Stage::Ir | Stage::Asm => "code generator",
+        };
+
+        Err(format!(
+            "can't emit {}, it needs the {missing}, which isn't implemented yet",
+            self.name()
+        ))
+    }
+
+    /// Text dump of this stage for every module of `graph`, in link order.
+    ///
+    /// Each dump starts with a `# stage <name>` line and each module with a
+    /// `# module <name>` line. Nothing in it depends on where the sources are,
+    /// so dumps can be compared between machines.
+    ///
+    /// The tokens dump has a `<start>..<end> <token>` line per token, with the
+    /// byte range of the token in the module source and the token as shown by
+    /// its [`Display`](std::fmt::Display) impl, like `4..8 identifier main`.
+    pub fn dump(self, graph: &ModuleGraph) -> Result<String, String> {
+        self.check_available()?;
+
+        let mut dump = format!("# stage {}\n", self.name());
+
+        for module in &graph.modules {
+            writeln!(dump, "\n# module {}", module.name).unwrap();
+
+            match self {
+                Stage::Tokens => {
+                    // Modules from the build cache have no tokens, lex them again.
+                    for token in Lexer::new(&module.source.code, 0) {
+                        let token = token.map_err(|err| format!("{}: {err}", module.name))?;
+                        writeln!(
+                            dump,
+                            "{}..{} {}",
+                            token.span.start, token.span.end, token.token
+                        )
+                        .unwrap();
+                    }
+                }
+                Stage::Bytecode => hex_dump(&package::module_image(module), &mut dump),
+                _ => unreachable!(),
+            }
+        }
+
+        Ok(dump)
+    }
+}
+
+/// Where `--emit` writes its dumps.
+#[derive(Default)]
+pub struct EmitOptions {
+    pub stages: Vec<Stage>,
+    /// Prints the dumps instead of writing them next to the output.
+    pub stdout: bool,
+}
+
+impl EmitOptions {
+    /// Fails if one of the stages can't be emitted yet, before anything is built.
+    pub fn check(&self) -> Result<(), String> {
+        self.stages
+            .iter()
+            .try_for_each(|stage| stage.check_available())
+    }
+
+    /// Dumps the stages of `graph`, to `<output>.<stage>.txt` files or to the
+    /// standard output, returning the files written.
+    pub fn emit(&self, graph: &ModuleGraph, output: &Path) -> Result<Vec<PathBuf>, String> {
+        let mut written = Vec::new();
+
+        for stage in &self.stages {
+            let dump = stage.dump(graph)?;
+
+            if self.stdout {
+                print!("{dump}");
+                continue;
+            }
+
+            let path = output.with_extension(format!("{}.txt", stage.name()));
+            fs::write(&path, dump)
+                .map_err(|err| format!("can't write '{}': {err}", path.display()))?;
+            written.push(path);
+        }
+
+        Ok(written)
+    }
+}
+
+/// Offset, hex bytes and printable ASCII of `bytes`, like `hexdump -C`.
+fn hex_dump(bytes: &[u8], dump: &mut String) {
+    for (line, chunk) in bytes.chunks(BYTES_PER_LINE).enumerate() {
+        write!(dump, "{:08x} ", line * BYTES_PER_LINE).unwrap();
+
+        for column in 0..BYTES_PER_LINE {
+            match chunk.get(column) {
+                Some(byte) => write!(dump, " {byte:02x}").unwrap(),
+                None => dump.push_str("   "),
+            }
+        }
+
+        dump.push_str("  |");
+        dump.extend(chunk.iter().map(|byte| match byte {
+            0x20..=0x7e => *byte as char,
+            _ => '.',
+        }));
+        dump.push_str("|\n");
+    }
+}
@@ -1,3 +1,135 @@
+//! Exit codes of `squidc` and the errors that end it.
+//!
+//! The exit codes are grouped by what went wrong:
+//!
+//! - usage errors, the command can't run as given: [`USAGE_ERR`],
+//!   [`FEATURE_ERR`] and [`PROJECT_ERR`]
+//! - compile errors, found in the code: [`COMPILE_ERR`] and [`FORMAT_ERR`]
+//! - I/O errors, reading or writing outside of the code failed:
+//!   [`UPDATE_CHECK_ERR`], [`BUNDLE_ERR`], [`PACKAGE_ERR`], [`CACHE_ERR`],
+//!   [`RUN_ERR`] and [`OUTPUT_ERR`]
+//! - internal errors, the compiler panicked: 101, set by Rust itself
+//!
+//! Compile errors have their own numbered codes, like `SQ0005`, see
+//! [`squidc::ErrorCode`].
+
+use squidc::project::BuildError;
+use squidc::{Diagnostics, ErrorCode};
+use std::fmt;
+
+/// The update server couldn't be reached or sent a bad response.
 pub const UPDATE_CHECK_ERR: i32 = 1;
 
-// pub const FEATURE_ERR:i32 = 2;
+/// Invalid arguments. Clap exits with it too when it can't parse them.
+pub const USAGE_ERR: i32 = 2;
+
+/// The command needs a part of the compiler that isn't implemented yet, a
+/// usage error.
+pub const FEATURE_ERR: i32 = USAGE_ERR;
+
+/// The code has errors.
+pub const COMPILE_ERR: i32 = 3;
+
+pub const BUNDLE_ERR: i32 = 4;
+
+pub const PACKAGE_ERR: i32 = 5;
+
+/// No manifest was found, or it or a dependency is invalid, a usage error.
+pub const PROJECT_ERR: i32 = 6;
+
+pub const CACHE_ERR: i32 = 7;
+
+/// The VM couldn't be started, otherwise `squidc run` exits with its code.
+pub const RUN_ERR: i32 = 8;
+
+/// `squidc fmt` found unformatted files, or files it couldn't format.
+pub const FORMAT_ERR: i32 = 9;
+
+/// An output file, like a compiled module, a bundle or the output of `doc -o`
+/// and `extract -o`, couldn't be written.
+pub const OUTPUT_ERR: i32 = 10;
+
+/// Exit codes as shown at the end of `squidc --help`.
+pub const EXIT_CODES_HELP: &str = "\
+Exit codes:
+  Usage errors:     2 invalid arguments or feature not implemented yet,
+                    6 missing or invalid manifest
+  Compile errors:   3 errors in the code, see --explain for their SQ codes,
+                    9 unformatted files
+  I/O errors:       1 update check, 4 bundle, 5 package, 7 build cache,
+                    8 the VM couldn't be started,
+                    10 an output file couldn't be written
+  Internal errors:  101 the compiler crashed, please report it
+
+`squidc run` otherwise exits with the code of the program.";
+
+/// Error that ends the program, `main` maps each kind to one of the exit
+/// codes above.
+#[derive(Debug)]
+pub enum Error {
+    UpdateCheck(String),
+    Usage(String),
+    /// The command needs a part of the compiler that isn't implemented yet.
+    Feature(String),
+    Compile(String),
+    Bundle(String),
+    Package(String),
+    Project(String),
+    Cache(String),
+    Run(String),
+    Format(String),
+    /// An output file couldn't be written.
+    Output(String),
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::UpdateCheck(message)
+            | Error::Usage(message)
+            | Error::Feature(message)
+            | Error::Compile(message)
+            | Error::Bundle(message)
+            | Error::Package(message)
+            | Error::Project(message)
+            | Error::Cache(message)
+            | Error::Run(message)
+            | Error::Format(message)
+            | Error::Output(message) => f.write_str(message),
+        }
+    }
+}
+
+impl From<BuildError> for Error {
+    fn from(err: BuildError) -> Self {
+        let message = err.to_string();
+
+        match err {
+            BuildError::Project(_) => Error::Project(message),
+            BuildError::Module(ref err) => {
+                Error::Compile(format!("{message}{}", explain_hint(&[err.code])))
+            }
+            BuildError::Cache(_) => Error::Cache(message),
+            BuildError::Bundle(_) => Error::Bundle(message),
+            BuildError::Output(_) => Error::Output(message),
+        }
+    }
+}
+
+impl From<Diagnostics> for Error {
+    fn from(diagnostics: Diagnostics) -> Self {
+        let codes: Vec<ErrorCode> = diagnostics.0.iter().map(|d| d.code).collect();
+
+        Error::Compile(format!("{diagnostics}{}", explain_hint(&codes)))
+    }
+}
+
+/// Points to `--explain` for the first of `codes`, like rustc does.
+fn explain_hint(codes: &[ErrorCode]) -> String {
+    match codes.first() {
+        Some(code) => {
+            format!("\nFor more information about this error, try `squidc --explain {code}`.")
+        }
+        None => String::new(),
+    }
+}
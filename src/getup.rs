@@ -1,37 +1,26 @@
-use crate::errdef::UPDATE_CHECK_ERR;
-use std::process;
+#[cfg(not(test))]
+use crate::errdef::Error;
 
 /// Gets update and returns Vector containing all strings necessary for displaying
 /// new versions, return all necessary information.
 #[cfg(not(test))]
-pub fn get_update() -> Vec<String> {
+pub fn get_update() -> Result<Vec<String>, Error> {
+    let error = |message: String| Error::UpdateCheck(format!("can't look for updates: {message}"));
+
     let mut mainvec = Vec::new();
     let response =
-        match minreq::get("https://api.github.com/repos/Fragmenta-Company/SquidC/releases/latest")
+        minreq::get("https://api.github.com/repos/Fragmenta-Company/SquidC/releases/latest")
             .with_header("User-Agent", "SquidC")
             .with_header("Accept", "application/vnd.github.v3+json")
             .send()
-        {
-            Ok(response) => response,
-            Err(err) => {
-                eprintln!("\x1B[41mError while looking for updates: {err}\x1b[0m");
-                process::exit(UPDATE_CHECK_ERR);
-            }
-        };
+            .map_err(|err| error(err.to_string()))?;
 
-    let json = match response.json::<serde_json::Value>() {
-        Ok(value) => value,
-        Err(err) => {
-            eprintln!("\x1B[41mError while looking for updates: {err}\x1b[0m");
-            process::exit(UPDATE_CHECK_ERR);
-        }
-    };
+    let json = response
+        .json::<serde_json::Value>()
+        .map_err(|err| error(err.to_string()))?;
     let tagname = json["tag_name"].as_str();
-    let parsed_data = if let Some(latest_version) = tagname {
-        latest_version
-    } else {
-        eprintln!("\x1B[41mError while looking for updates: Tag name is inexistent!\x1b[0m");
-        process::exit(UPDATE_CHECK_ERR);
+    let Some(parsed_data) = tagname else {
+        return Err(error(String::from("Tag name is inexistent!")));
     };
 
     let mut latest_version: String = String::from(parsed_data);
@@ -44,18 +33,17 @@ pub fn get_update() -> Vec<String> {
 
     let current_version: Vec<&str> = current_version.split(['.', '-']).collect();
 
-    let majors: [u32; 2] = [
-        latest_version[0].parse().unwrap(),
-        current_version[0].parse().unwrap(),
-    ];
-    let minors: [u32; 2] = [
-        latest_version[1].parse().unwrap(),
-        current_version[1].parse().unwrap(),
-    ];
-    let patchs: [u32; 2] = [
-        latest_version[2].parse().unwrap(),
-        current_version[2].parse().unwrap(),
-    ];
+    // Tags that aren't |major|.|minor|.|patch| are reported, not unwrapped.
+    let part = |version: &[&str], index: usize| -> Result<u32, Error> {
+        version
+            .get(index)
+            .and_then(|part| part.parse().ok())
+            .ok_or_else(|| error(format!("invalid version '{}'", version.join("."))))
+    };
+
+    let majors: [u32; 2] = [part(&latest_version, 0)?, part(&current_version, 0)?];
+    let minors: [u32; 2] = [part(&latest_version, 1)?, part(&current_version, 1)?];
+    let patchs: [u32; 2] = [part(&latest_version, 2)?, part(&current_version, 2)?];
 
     let mut new_ver = 0;
     let mut versioning: Vec<bool> = Vec::new();
@@ -123,14 +111,14 @@ pub fn get_update() -> Vec<String> {
         let newer = newer.map_err(|err| format!("\x1B[41m{err}\x1B[0m"));
 
         if let Ok(newer) = newer {
-            mainvec.push(format!("{newer}"));
+            mainvec.push(newer.to_string());
         } else if let Err(err) = newer {
             mainvec.push(format!("\x1B[41m{err}\x1b[0m"));
         }
     }
 
     if has_new_ver || details_different {
-        mainvec.push(format!("{new}"));
+        mainvec.push(new.to_string());
         let mut setter: Vec<&str> = Vec::new();
         if versioning[2] {
             versioning[1] = true;
@@ -189,7 +177,7 @@ pub fn get_update() -> Vec<String> {
         });
     }
 
-    mainvec
+    Ok(mainvec)
 }
 
 /// Gets update and returns Vector containing all strings necessary for displaying
@@ -207,11 +195,9 @@ pub fn get_update() {
     let tagname = json["tag_name"].as_str();
     let parsed_data = tagname.unwrap();
 
-    let mut latest_version: String = String::from(parsed_data);
-
-    latest_version = String::from(latest_version.trim_start_matches("V"));
+    assert!(!parsed_data.trim_start_matches('V').is_empty());
 
-    latest_version = String::from("10000.10000.10000-rc1");
+    let latest_version = String::from("10000.10000.10000-rc1");
 
     let latest_version: Vec<&str> = latest_version.split(['.', '-']).collect();
 
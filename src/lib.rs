@@ -0,0 +1,32 @@
+//! The Squid compiler as a library, for build tools and test suites that
+//! compile Squid code in-process.
+//!
+//! [`Session`], [`compile`] and the types they use are the stable API:
+//!
+//! ```no_run
+//! let options = squidc::Options::default();
+//! let mut session = squidc::Session::new(options);
+//! session.add_source("greet.sq", "pub fn hello() { println(\"hi\"); }");
+//!
+//! match session.compile("import greet::hello;\nfn main() { hello(); }") {
+//!     Ok(artifact) => println!("{} bytes of bytecode", artifact.bytecode.len()),
+//!     Err(diagnostics) => eprintln!("{diagnostics}"),
+//! }
+//! ```
+//!
+//! The other modules are what the `squidc` command line is built on. They're
+//! public so it can use them, but they change with the compiler.
+
+pub mod bundle;
+pub mod cache;
+mod codes;
+pub mod compiler_internals;
+pub mod doc;
+pub mod emit;
+pub mod package;
+pub mod project;
+mod session;
+pub mod watch;
+
+pub use codes::ErrorCode;
+pub use session::{compile, Artifact, Diagnostic, Diagnostics, Options, Session};
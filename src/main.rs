@@ -1,30 +1,56 @@
-use crate::argsdef::Args;
-use clap::Parser;
+// `main` and the argument handling are compiled out of test builds, so are
+// the imports only they use.
+#[cfg(not(test))]
+use crate::argsdef::{Args, CacheAction, Command, SelfAction};
+#[cfg(not(test))]
+use crate::errdef::Error;
+#[cfg(not(test))]
+use clap::{CommandFactory, Parser};
+#[cfg(not(test))]
+use squidc::compiler_internals::modules::ModuleGraph;
+#[cfg(not(test))]
+use squidc::compiler_internals::Lexer;
+#[cfg(not(test))]
+use squidc::{bundle, cache, compiler_internals, doc, emit, package, project, watch};
+#[cfg(not(test))]
+use std::path::{Path, PathBuf};
 use std::sync::Arc;
 use std::{process, thread};
 
 mod argsdef;
-mod compiler_internals;
+#[cfg_attr(test, allow(dead_code))]
 mod errdef;
+#[cfg_attr(test, allow(dead_code))]
 mod getup;
 
 #[macro_use]
 mod macrodefs;
+#[cfg_attr(test, allow(dead_code))]
 mod targetdef;
 
+#[cfg(not(test))]
 const VM_NAMING_CONVENTION: &str = "Squid Compiler";
 
-/// Contains tools for checking updates, getting current version and others.
+/// Prints the current version and what's new in the latest release.
 #[cfg(not(test))]
-fn version_args(args: &Args) {
-    if args.check_updates {
-        println!("Current version: {}", env!("CARGO_PKG_VERSION"));
+fn check_updates() -> Result<(), Error> {
+    println!("Current version: {}", env!("CARGO_PKG_VERSION"));
+
+    getup::get_update()?.iter().rev().for_each(move |string| {
+        println!("{string}");
+    });
 
-        getup::get_update().iter().rev().for_each(move |string| {
-            println!("{string}");
-        });
+    Ok(())
+}
 
-        process::exit(0);
+/// Contains tools for checking updates, getting current version and others.
+///
+/// Returns whether one of them was asked for, then there's nothing else to do.
+#[cfg(not(test))]
+fn version_args(args: &Args) -> Result<bool, Error> {
+    if args.check_updates {
+        check_updates()?;
+        return Ok(true);
     }
 
     use crate::targetdef::TARGET;
@@ -38,29 +64,649 @@ fn version_args(args: &Args) {
             TARGET
         );
         dev_print!("---- SVDK ---- ---- SVDK ---- SVDK ---- ---- SVDK ----");
-        process::exit(0);
+        return Ok(true);
     }
+
+    Ok(false)
 }
 
+/// The only place the process exits, with the code of its [`Error`] if any.
 #[cfg(not(test))]
 fn main() {
-    compiler_internals::tokenizer::test();
+    simple_logger::init_with_level(log::Level::Warn).unwrap();
 
     let args = Args::parse();
 
-    version_args(&args);
+    match run(&args) {
+        Ok(code) => process::exit(code),
+        Err(err) => {
+            eprintln!("\x1B[41mError: {err}\x1b[0m");
+            process::exit(match err {
+                Error::UpdateCheck(_) => errdef::UPDATE_CHECK_ERR,
+                Error::Usage(_) => errdef::USAGE_ERR,
+                Error::Feature(_) => errdef::FEATURE_ERR,
+                Error::Compile(_) => errdef::COMPILE_ERR,
+                Error::Bundle(_) => errdef::BUNDLE_ERR,
+                Error::Package(_) => errdef::PACKAGE_ERR,
+                Error::Project(_) => errdef::PROJECT_ERR,
+                Error::Cache(_) => errdef::CACHE_ERR,
+                Error::Run(_) => errdef::RUN_ERR,
+                Error::Format(_) => errdef::FORMAT_ERR,
+                Error::Output(_) => errdef::OUTPUT_ERR,
+            });
+        }
+    }
+}
+
+/// Runs the command given in `args`, returning the exit code on success.
+#[cfg(not(test))]
+fn run(args: &Args) -> Result<i32, Error> {
+    if version_args(args)? {
+        return Ok(0);
+    }
+
+    if let Some(code) = &args.explain {
+        explain(code)?;
+        return Ok(0);
+    }
+
+    #[cfg(feature = "devkit")]
+    if args.bench_frontend {
+        compiler_internals::frontend::bench();
+        return Ok(0);
+    }
+
+    #[cfg(feature = "devkit")]
+    if args.bench_lexer {
+        compiler_internals::tokenizer::bench();
+        return Ok(0);
+    }
+
+    let Some(command) = &args.command else {
+        Args::command().print_help().unwrap();
+        return Ok(0);
+    };
+
+    match command {
+        Command::Build {
+            input: Some(input),
+            optimizations,
+            bundle,
+            vm,
+            output,
+            emit,
+            emit_stdout,
+            ..
+        } => build_file(
+            input,
+            optimizations.unwrap_or(0),
+            *bundle,
+            vm.as_deref(),
+            output.as_deref(),
+            &emit_options(emit, *emit_stdout)?,
+        ),
+        Command::Build {
+            input: None,
+            manifest_path,
+            optimizations,
+            bundle,
+            vm,
+            output: _,
+            no_cache,
+            watch,
+            emit,
+            emit_stdout,
+        } => {
+            let options = project::BuildOptions {
+                optimizations: *optimizations,
+                bundle: *bundle,
+                vm: vm.clone(),
+                no_cache: *no_cache,
+            };
+            let emit = emit_options(emit, *emit_stdout)?;
+            match watch {
+                true => watch_project(manifest_path.as_deref(), &options, &emit),
+                false => build_project(manifest_path.as_deref(), &options, &emit),
+            }
+        }
+        Command::Check {
+            input,
+            manifest_path,
+        } => check(input.as_deref(), manifest_path.as_deref()),
+        Command::Run {
+            manifest_path,
+            optimizations,
+            vm,
+            no_cache,
+            args,
+        } => {
+            let options = project::BuildOptions {
+                optimizations: *optimizations,
+                bundle: false,
+                vm: vm.clone(),
+                no_cache: *no_cache,
+            };
+            return run_project(manifest_path.as_deref(), &options, args);
+        }
+        Command::Tokens { file, trivia } => print_tokens(file, *trivia),
+        Command::Ast { .. } => Err(Error::Feature(String::from(
+            "printing syntax trees needs the parser, which isn't implemented yet",
+        ))),
+        Command::Disasm { file } => disassemble(file),
+        Command::Fmt { files, check } => format_files(files, *check),
+        Command::Doc {
+            input,
+            manifest_path,
+            output,
+        } => document(
+            input.as_deref(),
+            manifest_path.as_deref(),
+            output.as_deref(),
+        ),
+        Command::Test { manifest_path } => {
+            load_project(manifest_path.as_deref())?;
+            Err(Error::Feature(String::from(
+                "running tests needs the code generator, which isn't implemented yet",
+            )))
+        }
+        Command::Repl => Err(Error::Feature(String::from(
+            "the REPL needs the parser and the code generator, which aren't implemented yet",
+        ))),
+        Command::Installation {
+            action: SelfAction::Update,
+        }
+        | Command::Update => check_updates(),
+        Command::Extract { bundle, output } => extract_bundle(bundle, output.as_deref()),
+        Command::Cache {
+            action: CacheAction::Clean,
+            manifest_path,
+        } => clean_cache(manifest_path.as_deref()),
+        Command::Package { input, output } => build_package(input, output.as_deref()),
+        Command::InspectPackage { package } => inspect_package(package),
+    }?;
+
+    Ok(0)
+}
+
+/// Prints the long explanation of a compiler error code.
+#[cfg(not(test))]
+fn explain(code: &str) -> Result<(), Error> {
+    let code = squidc::ErrorCode::parse(code).ok_or_else(|| {
+        Error::Usage(format!(
+            "'{code}' isn't a Squid error code, they go from {} to {}",
+            squidc::ErrorCode::ALL[0],
+            squidc::ErrorCode::ALL[squidc::ErrorCode::ALL.len() - 1]
+        ))
+    })?;
+
+    print!("{}", code.explanation());
+
+    Ok(())
+}
+
+/// Compiles `input` and its imports.
+#[cfg(not(test))]
+fn compile_file(input: &Path, optimizations: u8) -> Result<squidc::Artifact, Error> {
+    let mut options = squidc::Options::default();
+    options.optimizations = optimizations;
+    let session = squidc::Session::new(options);
+
+    let artifact = session.compile_file(input)?;
+    print_warnings(&artifact.warnings);
+
+    Ok(artifact)
+}
+
+/// Prints warnings found while compiling, they don't stop it.
+#[cfg(not(test))]
+fn print_warnings<T: std::fmt::Display>(warnings: impl IntoIterator<Item = T>) {
+    for warning in warnings {
+        eprintln!("\x1B[43mWarning: {warning}\x1b[0m");
+    }
+}
+
+/// Checks the stages given to `--emit` can be emitted, before building.
+#[cfg(not(test))]
+fn emit_options(stages: &[emit::Stage], stdout: bool) -> Result<emit::EmitOptions, Error> {
+    let options = emit::EmitOptions {
+        stages: stages.to_vec(),
+        stdout,
+    };
+
+    options.check().map_err(Error::Feature)?;
+
+    Ok(options)
+}
+
+/// Writes the `--emit` dumps of `graph` next to `output`.
+#[cfg(not(test))]
+fn emit_stages(emit: &emit::EmitOptions, graph: &ModuleGraph, output: &Path) -> Result<(), String> {
+    for path in emit.emit(graph, output)? {
+        println!("Emitted {}", path.display());
+    }
+
+    Ok(())
+}
+
+/// Compiles a single entry file and its imports, bundling them with `vm`
+/// when asked to.
+#[cfg(not(test))]
+fn build_file(
+    input: &Path,
+    optimizations: u8,
+    bundle: bool,
+    vm: Option<&Path>,
+    output: Option<&Path>,
+    emit: &emit::EmitOptions,
+) -> Result<(), Error> {
+    let mut artifact = compile_file(input, optimizations)?;
+    let modules = std::mem::take(&mut artifact.modules);
+    let payload = std::mem::take(&mut artifact.bytecode);
+    let graph = artifact.into_graph();
+
+    log::info!("Link order: {}", modules.join(", "));
+
+    if bundle {
+        let Some(vm) = vm else {
+            return Err(Error::Bundle(String::from(
+                "bundling needs the VM executable, pass --vm",
+            )));
+        };
+
+        let executable = bundle_executable(vm, input, output, &payload)?;
+        return emit_stages(emit, &graph, &executable).map_err(Error::Output);
+    }
+
+    let output = output
+        .map(Path::to_path_buf)
+        .unwrap_or_else(|| input.with_extension("sqb"));
+
+    std::fs::write(&output, payload)
+        .map_err(|err| Error::Output(format!("can't write '{}': {err}", output.display())))?;
+
+    println!(
+        "Compiled {} ({} modules, {optimizations} optimization steps) into {}",
+        input.display(),
+        graph.modules.len(),
+        output.display()
+    );
+
+    emit_stages(emit, &graph, &output).map_err(Error::Output)
+}
+
+/// Runs the front end on `input`, or on the given or nearest project.
+#[cfg(not(test))]
+fn check(input: Option<&Path>, manifest_path: Option<&Path>) -> Result<(), Error> {
+    let (name, modules) = match input {
+        Some(input) => (
+            input.display().to_string(),
+            compile_file(input, 0)?.modules.len(),
+        ),
+        None => {
+            let project = load_project(manifest_path)?;
+            let graph = project::check(&project)?;
+            print_warnings(graph.warnings());
+            (project.manifest.package.name, graph.modules.len())
+        }
+    };
+
+    println!("Checked {name} ({modules} modules), no errors");
+
+    Ok(())
+}
+
+/// Builds the given or nearest project and runs its image with the VM,
+/// returning the exit code of the VM.
+#[cfg(not(test))]
+fn run_project(
+    manifest_path: Option<&Path>,
+    options: &project::BuildOptions,
+    args: &[String],
+) -> Result<i32, Error> {
+    let project = load_project(manifest_path)?;
+
+    let Some(vm) = project::vm(&project, options) else {
+        return Err(Error::Run(String::from(
+            "running needs the VM executable, set 'vm' in [bundle] or pass --vm",
+        )));
+    };
+
+    let image = project::build(&project, options)?.image;
+
+    let status = process::Command::new(&vm)
+        .arg(&image)
+        .args(args)
+        .status()
+        .map_err(|err| Error::Run(format!("can't run the VM at '{}': {err}", vm.display())))?;
+
+    Ok(status.code().unwrap_or(errdef::RUN_ERR))
+}
+
+/// Prints the tokens of `file` with their spans, and their trivia if asked.
+#[cfg(not(test))]
+fn print_tokens(file: &Path, trivia: bool) -> Result<(), Error> {
+    let code = std::fs::read_to_string(file)
+        .map_err(|err| Error::Compile(format!("can't read '{}': {err}", file.display())))?;
+
+    let lexer = match trivia {
+        true => Lexer::new(&code, 0).lossless(),
+        false => Lexer::new(&code, 0),
+    };
 
-    let optimizations = args.optimizations;
+    compiler_internals::validate_source(&code)
+        .and_then(|_| {
+            for (counter, token) in lexer.enumerate() {
+                let token = token?;
 
-    if let Some(o) = optimizations {
-        match o {
-            0 => {}
-            1 => {}
-            2 => {}
-            3 => {}
-            _ => {}
+                for trivia in &token.trivia {
+                    println!("  {:?} {:?}", trivia.kind, &code[trivia.span.clone()]);
+                }
+                println!("Token {counter}: {:?} at {:?}", token.token, token.span);
+            }
+
+            Ok(())
+        })
+        .map_err(|err| Error::Compile(format!("{}: {err}", file.display())))
+}
+
+/// Lists the modules of compiled bytecode, or of the payload of a bundle,
+/// with the code they hold.
+#[cfg(not(test))]
+fn disassemble(file: &Path) -> Result<(), Error> {
+    let bytes = std::fs::read(file)
+        .map_err(|err| Error::Compile(format!("can't read '{}': {err}", file.display())))?;
+
+    // Files without the bundle magic are plain images, like a `.sqb`.
+    let image = match bundle::is_bundle(&bytes) {
+        true => bundle::find_payload(&bytes)
+            .map_err(|err| Error::Bundle(format!("'{}' {err}", file.display())))?,
+        false => &bytes[..],
+    };
+
+    let modules = package::split_image(image)
+        .map_err(|err| Error::Compile(format!("'{}' {err}", file.display())))?;
+
+    for (name, code) in &modules {
+        println!("module {name} ({} bytes)", code.len());
+
+        for (line, text) in String::from_utf8_lossy(code).lines().enumerate() {
+            println!("{:>5} | {text}", line + 1);
+        }
+        println!();
+    }
+
+    println!("{}: {} modules", file.display(), modules.len());
+
+    Ok(())
+}
+
+/// Formats `files`, or the sources of the nearest project, in place.
+#[cfg(not(test))]
+fn format_files(files: &[PathBuf], check: bool) -> Result<(), Error> {
+    let files = match files.is_empty() {
+        true => project::sources(&load_project(None)?)?,
+        false => files.to_vec(),
+    };
+
+    let mut failed = 0;
+
+    for file in &files {
+        let result = std::fs::read_to_string(file)
+            .map_err(|err| format!("can't read '{}': {err}", file.display()))
+            .and_then(|code| {
+                let formatted = compiler_internals::format::format(&code)
+                    .map_err(|err| format!("{}: {err}", file.display()))?;
+                Ok((code, formatted))
+            });
+
+        match result {
+            Ok((code, formatted)) if code == formatted => {}
+            Ok(_) if check => {
+                println!("{} isn't formatted", file.display());
+                failed += 1;
+            }
+            Ok((_, formatted)) => match std::fs::write(file, formatted) {
+                Ok(()) => println!("Formatted {}", file.display()),
+                Err(err) => {
+                    eprintln!(
+                        "\x1B[41mError: can't write '{}': {err}\x1b[0m",
+                        file.display()
+                    );
+                    failed += 1;
+                }
+            },
+            Err(err) => {
+                eprintln!("\x1B[41mError: {err}\x1b[0m");
+                failed += 1;
+            }
+        }
+    }
+
+    match failed {
+        0 => Ok(()),
+        _ => Err(Error::Format(format!(
+            "{failed} of {} files aren't formatted",
+            files.len()
+        ))),
+    }
+}
+
+/// Writes the documentation of `input`, or of the given or nearest project.
+#[cfg(not(test))]
+fn document(
+    input: Option<&Path>,
+    manifest_path: Option<&Path>,
+    output: Option<&Path>,
+) -> Result<(), Error> {
+    let (title, graph) = match input {
+        Some(input) => (
+            input
+                .file_stem()
+                .unwrap_or_default()
+                .to_string_lossy()
+                .into_owned(),
+            compile_file(input, 0)?.into_graph(),
+        ),
+        None => {
+            let project = load_project(manifest_path)?;
+            let graph = project::check(&project)?;
+            print_warnings(graph.warnings());
+            (project.manifest.package.name, graph)
+        }
+    };
+
+    let markdown = doc::markdown(&title, &graph).map_err(|err| Error::Compile(err.to_string()))?;
+
+    match output {
+        Some(output) => {
+            std::fs::write(output, markdown).map_err(|err| {
+                Error::Output(format!("can't write '{}': {err}", output.display()))
+            })?;
+            println!("Documented {title} into {}", output.display());
+        }
+        None => print!("{markdown}"),
+    }
+
+    Ok(())
+}
+
+/// Writes `payload` into a copy of `vm`.
+#[cfg(not(test))]
+fn bundle_executable(
+    vm: &Path,
+    input: &Path,
+    output: Option<&Path>,
+    payload: &[u8],
+) -> Result<PathBuf, Error> {
+    let output = output.map(Path::to_path_buf).unwrap_or_else(|| {
+        let name = input.file_stem().unwrap_or_default().to_string_lossy();
+        PathBuf::from(format!("{name}{}", std::env::consts::EXE_SUFFIX))
+    });
+
+    bundle::bundle(vm, payload, &output).map_err(Error::Bundle)?;
+
+    println!("Bundled {} into {}", input.display(), output.display());
+
+    Ok(output)
+}
+
+/// Checks the payload of a bundle, writing it to `output` when given.
+#[cfg(not(test))]
+fn extract_bundle(bundle: &Path, output: Option<&Path>) -> Result<(), Error> {
+    let payload = bundle::extract(bundle).map_err(Error::Bundle)?;
+
+    println!(
+        "{}: {} byte payload, checksum OK",
+        bundle.display(),
+        payload.len()
+    );
+
+    if let Some(output) = output {
+        std::fs::write(output, payload)
+            .map_err(|err| Error::Output(format!("can't write '{}': {err}", output.display())))?;
+    }
+
+    Ok(())
+}
+
+/// The given manifest, or the nearest one.
+#[cfg(not(test))]
+fn manifest_path(manifest_path: Option<&Path>) -> Result<PathBuf, Error> {
+    match manifest_path {
+        Some(path) => Ok(path.to_path_buf()),
+        None => std::env::current_dir()
+            .ok()
+            .and_then(|directory| project::find_manifest(&directory))
+            .ok_or_else(|| {
+                Error::Project(format!(
+                    "no {} found in this directory or its parents",
+                    project::MANIFEST_NAME
+                ))
+            }),
+    }
+}
+
+/// Loads the project of the given or nearest manifest.
+#[cfg(not(test))]
+fn load_project(manifest_path_arg: Option<&Path>) -> Result<project::Project, Error> {
+    Ok(project::load(&manifest_path(manifest_path_arg)?)?)
+}
+
+/// Builds the project of the given or nearest manifest.
+#[cfg(not(test))]
+fn build_project(
+    manifest_path: Option<&Path>,
+    options: &project::BuildOptions,
+    emit: &emit::EmitOptions,
+) -> Result<(), Error> {
+    let project = load_project(manifest_path)?;
+
+    Ok(run_build(&project, options, emit)?)
+}
+
+/// Builds the project of the given or nearest manifest, and again each time
+/// its files change, until interrupted. Errors are printed, not fatal.
+#[cfg(not(test))]
+fn watch_project(
+    manifest_path_arg: Option<&Path>,
+    options: &project::BuildOptions,
+    emit: &emit::EmitOptions,
+) -> Result<(), Error> {
+    let manifest_path = manifest_path(manifest_path_arg)?;
+    // Until the project loads, only its manifest is watched.
+    let mut watched = vec![manifest_path.clone()];
+
+    loop {
+        match project::load(&manifest_path) {
+            Ok(project) => {
+                watched = watch::watched_paths(&project);
+
+                if let Err(err) = run_build(&project, options, emit) {
+                    eprintln!("\x1B[41mError: {err}\x1b[0m");
+                }
+            }
+            Err(err) => eprintln!("\x1B[41mError: {err}\x1b[0m"),
         }
+
+        println!("Watching for changes, press Ctrl+C to stop");
+        watch::wait_for_changes(&watched);
+        println!();
+    }
+}
+
+/// Builds `project`, prints what was built and emits the dumps asked for.
+#[cfg(not(test))]
+fn run_build(
+    project: &project::Project,
+    options: &project::BuildOptions,
+    emit: &emit::EmitOptions,
+) -> Result<(), project::BuildError> {
+    let output = project::build(project, options)?;
+    print_warnings(output.graph.warnings());
+
+    let package = &project.manifest.package;
+    println!(
+        "Compiled {} v{} ({} modules, {} recompiled, {} optimization steps) into {}",
+        package.name,
+        package.version,
+        output.graph.modules.len(),
+        output.recompiled,
+        output.optimizations,
+        output.image.display()
+    );
+
+    if let Some(executable) = &output.executable {
+        println!("Bundled into {}", executable.display());
+    }
+
+    emit_stages(emit, &output.graph, &output.image).map_err(project::BuildError::Output)
+}
+
+/// Deletes the build cache of the given or nearest project.
+#[cfg(not(test))]
+fn clean_cache(manifest_path: Option<&Path>) -> Result<(), Error> {
+    let project = load_project(manifest_path)?;
+
+    match cache::clean(&project.target_dir()).map_err(Error::Cache)? {
+        true => println!("Removed the build cache"),
+        false => println!("There's no build cache to remove"),
+    }
+
+    Ok(())
+}
+
+/// Loads the modules of `input` and writes them to a package archive.
+#[cfg(not(test))]
+fn build_package(input: &Path, output: Option<&Path>) -> Result<(), Error> {
+    let graph = compile_file(input, 0)?.into_graph();
+
+    let output = output
+        .map(Path::to_path_buf)
+        .unwrap_or_else(|| input.with_extension(package::PACKAGE_EXTENSION));
+
+    package::package(input, &graph, &output).map_err(Error::Package)?;
+
+    println!("Packaged {} into {}", input.display(), output.display());
+
+    Ok(())
+}
+
+/// Lists the entries of a package archive after validating it.
+#[cfg(not(test))]
+fn inspect_package(path: &Path) -> Result<(), Error> {
+    let entries =
+        package::inspect(path).map_err(|err| Error::Package(format!("invalid package, {err}")))?;
+
+    for entry in &entries {
+        println!("{:>10}  {}", entry.size, entry.name);
     }
+    println!(
+        "{}: {} entries, valid package",
+        path.display(),
+        entries.len()
+    );
+
+    Ok(())
 }
 
 fn _unused() {
@@ -0,0 +1,342 @@
+use crate::compiler_internals::modules::{Module, ModuleGraph};
+use byteorder::{ByteOrder, LittleEndian, WriteBytesExt};
+use std::collections::HashMap;
+use std::fs::{self, File};
+use std::io::{Read, Write};
+use std::path::{Path, PathBuf};
+use zip::write::FileOptions;
+use zip::{ZipArchive, ZipWriter};
+
+/// Extension of Squid package archives.
+pub const PACKAGE_EXTENSION: &str = "sqpkg";
+
+/// Version of the package layout, bumped on incompatible changes.
+const FORMAT: u64 = 2;
+
+const MANIFEST: &str = "manifest.json";
+const CHECKSUMS: &str = "checksums.txt";
+
+/// Directory next to the entry file whose files are packaged as resources.
+const RESOURCES: &str = "resources";
+
+/// Compiled form of a module.
+///
+/// There's no code generator yet, so for now it's the module name, a NUL, the
+/// source length (u64) and the source.
+pub fn module_image(module: &Module) -> Vec<u8> {
+    let mut image = Vec::new();
+
+    image.extend_from_slice(module.name.as_bytes());
+    image.push(0);
+    image
+        .write_u64::<LittleEndian>(module.source.code.len() as u64)
+        .unwrap();
+    image.extend_from_slice(module.source.code.as_bytes());
+
+    image
+}
+
+/// Splits an image made of [`module_image`]s back into the name and code of
+/// each module.
+pub fn split_image(mut image: &[u8]) -> Result<Vec<(String, &[u8])>, String> {
+    let mut modules = Vec::new();
+
+    while !image.is_empty() {
+        let name_end = image
+            .iter()
+            .position(|byte| *byte == 0)
+            .ok_or("has a module without a name")?;
+        let name = String::from_utf8_lossy(&image[..name_end]).into_owned();
+
+        let rest = &image[name_end + 1..];
+        let length = rest
+            .get(..8)
+            .map(LittleEndian::read_u64)
+            .ok_or_else(|| format!("has a truncated header for module '{name}'"))?;
+
+        let code = usize::try_from(length)
+            .ok()
+            .and_then(|length| 8usize.checked_add(length))
+            .and_then(|end| rest.get(8..end))
+            .ok_or_else(|| format!("has truncated code for module '{name}'"))?;
+
+        image = &rest[8 + code.len()..];
+        modules.push((name, code));
+    }
+
+    Ok(modules)
+}
+
+/// Path of a module in the archive, like `modules/app/math.sqb` for `app::math`.
+fn module_entry(directory: &str, name: &str, extension: &str) -> String {
+    format!("{directory}/{}.{extension}", name.replace("::", "/"))
+}
+
+/// Writes a package archive with the project modules of `graph`.
+///
+/// It holds:
+/// - `manifest.json`: format, name, compiler version, entry and modules
+/// - `modules/*.sqb`: one compiled image per module
+/// - `debug/*.json`: source path and line offsets of each module
+/// - `resources/*`: the files of the `resources` directory next to `entry`
+/// - `checksums.txt`: CRC-32 of the code in each module image, as
+///   `crc32  module` lines
+///
+/// Standard library modules ship with the compiler, they're left out.
+pub fn package(entry: &Path, graph: &ModuleGraph, output: &Path) -> Result<(), String> {
+    let file = File::create(output)
+        .map_err(|err| format!("can't create '{}': {err}", output.display()))?;
+    let zip_err = |err: zip::result::ZipError| format!("can't write '{}': {err}", output.display());
+    let io_err = |err: std::io::Error| format!("can't write '{}': {err}", output.display());
+
+    let mut zip = ZipWriter::new(file);
+    let options = FileOptions::default();
+
+    let modules: Vec<&Module> = graph
+        .modules
+        .iter()
+        .filter(|module| !module.name.starts_with("std::"))
+        .collect();
+
+    let manifest = serde_json::json!({
+        "format": FORMAT,
+        "name": entry.file_stem().unwrap_or_default().to_string_lossy(),
+        "compiler": env!("CARGO_PKG_VERSION"),
+        "entry": modules.last().map(|module| &module.name),
+        "modules": modules.iter().map(|module| &module.name).collect::<Vec<_>>(),
+    });
+
+    zip.start_file(MANIFEST, options).map_err(zip_err)?;
+    serde_json::to_writer_pretty(&mut zip, &manifest)
+        .map_err(|err| format!("can't write '{}': {err}", output.display()))?;
+
+    let mut checksums = String::new();
+
+    for module in &modules {
+        zip.start_file(module_entry("modules", &module.name, "sqb"), options)
+            .map_err(zip_err)?;
+        zip.write_all(&module_image(module)).map_err(io_err)?;
+
+        let lines: Vec<usize> = std::iter::once(0)
+            .chain(module.source.code.match_indices('\n').map(|(i, _)| i + 1))
+            .collect();
+        let debug = serde_json::json!({
+            "source": module.source.path,
+            "lines": lines,
+        });
+
+        zip.start_file(module_entry("debug", &module.name, "json"), options)
+            .map_err(zip_err)?;
+        serde_json::to_writer(&mut zip, &debug)
+            .map_err(|err| format!("can't write '{}': {err}", output.display()))?;
+
+        checksums.push_str(&format!(
+            "{:08x}  {}\n",
+            crc32fast::hash(module.source.code.as_bytes()),
+            module.name
+        ));
+    }
+
+    zip.start_file(CHECKSUMS, options).map_err(zip_err)?;
+    zip.write_all(checksums.as_bytes()).map_err(io_err)?;
+
+    let resources = entry.parent().unwrap_or(Path::new("")).join(RESOURCES);
+    if resources.is_dir() {
+        for path in files_in(&resources)? {
+            let relative = path.strip_prefix(&resources).unwrap();
+            let name = format!(
+                "{RESOURCES}/{}",
+                relative.to_string_lossy().replace('\\', "/")
+            );
+            let contents =
+                fs::read(&path).map_err(|err| format!("can't read '{}': {err}", path.display()))?;
+
+            zip.start_file(name, options).map_err(zip_err)?;
+            zip.write_all(&contents).map_err(io_err)?;
+        }
+    }
+
+    zip.finish().map_err(zip_err)?;
+
+    Ok(())
+}
+
+/// Every file under `directory`, recursively, in a stable order.
+fn files_in(directory: &Path) -> Result<Vec<PathBuf>, String> {
+    let mut files = Vec::new();
+    let entries = fs::read_dir(directory)
+        .map_err(|err| format!("can't read '{}': {err}", directory.display()))?;
+
+    for entry in entries {
+        let path = entry
+            .map_err(|err| format!("can't read '{}': {err}", directory.display()))?
+            .path();
+
+        if path.is_dir() {
+            files.extend(files_in(&path)?);
+        } else {
+            files.push(path);
+        }
+    }
+
+    files.sort();
+    Ok(files)
+}
+
+/// Entry of a package archive.
+pub struct PackageEntry {
+    pub name: String,
+    pub size: u64,
+}
+
+/// Reads every entry of a package, which makes the zip crate check their
+/// CRCs, checks the manifest lists modules that are all there and that the
+/// code in each module image matches its line of `checksums.txt`.
+pub fn inspect(package: &Path) -> Result<Vec<PackageEntry>, String> {
+    let err = |message: String| format!("'{}' {message}", package.display());
+
+    let file = File::open(package).map_err(|e| err(format!("can't be opened: {e}")))?;
+    let mut archive =
+        ZipArchive::new(file).map_err(|e| err(format!("isn't a package archive: {e}")))?;
+
+    let mut entries = Vec::new();
+    let mut manifest = None;
+    let mut checksums = String::new();
+    let mut images = HashMap::new();
+
+    for index in 0..archive.len() {
+        let mut entry = archive
+            .by_index(index)
+            .map_err(|e| err(format!("has an unreadable entry: {e}")))?;
+
+        let mut contents = Vec::new();
+        entry
+            .read_to_end(&mut contents)
+            .map_err(|e| err(format!("has a corrupted entry '{}': {e}", entry.name())))?;
+
+        match entry.name() {
+            MANIFEST => {
+                let value: serde_json::Value = serde_json::from_slice(&contents)
+                    .map_err(|e| err(format!("has an invalid manifest: {e}")))?;
+                manifest = Some(value);
+            }
+            CHECKSUMS => checksums = String::from_utf8_lossy(&contents).into_owned(),
+            _ => {}
+        }
+
+        entries.push(PackageEntry {
+            name: entry.name().to_string(),
+            size: entry.size(),
+        });
+
+        if entry.name().starts_with("modules/") {
+            images.insert(entry.name().to_string(), contents);
+        }
+    }
+
+    let Some(manifest) = manifest else {
+        return Err(err(format!("has no {MANIFEST}")));
+    };
+
+    if manifest["format"].as_u64() != Some(FORMAT) {
+        return Err(err(format!(
+            "uses package format {}, this compiler reads format {FORMAT}",
+            manifest["format"]
+        )));
+    }
+
+    let Some(modules) = manifest["modules"].as_array() else {
+        return Err(err(String::from("has a manifest without a module list")));
+    };
+
+    let mut expected = HashMap::new();
+
+    for line in checksums.lines() {
+        let checksum = line
+            .split_once("  ")
+            .and_then(|(crc, name)| Some((u32::from_str_radix(crc, 16).ok()?, name)));
+
+        let Some((crc, name)) = checksum else {
+            return Err(err(format!("has an invalid {CHECKSUMS} line '{line}'")));
+        };
+
+        if expected.insert(name, crc).is_some() {
+            return Err(err(format!("lists module '{name}' twice in {CHECKSUMS}")));
+        }
+    }
+
+    for module in modules {
+        let name = module
+            .as_str()
+            .ok_or_else(|| err(String::from("has a manifest with an invalid module name")))?;
+
+        let Some(crc) = expected.remove(name) else {
+            return Err(err(format!(
+                "has no checksum for module '{name}' in {CHECKSUMS}"
+            )));
+        };
+
+        for required in [
+            module_entry("modules", name, "sqb"),
+            module_entry("debug", name, "json"),
+        ] {
+            if !entries.iter().any(|entry| entry.name == required) {
+                return Err(err(format!("is missing '{required}' for module '{name}'")));
+            }
+        }
+
+        let image = &images[&module_entry("modules", name, "sqb")];
+        let code = match split_image(image) {
+            Ok(split) if split.len() == 1 && split[0].0 == name => split[0].1,
+            Ok(_) => return Err(err(format!("has an image that isn't just module '{name}'"))),
+            Err(e) => return Err(err(format!("has an image that {e}"))),
+        };
+
+        if crc32fast::hash(code) != crc {
+            return Err(err(format!(
+                "has module '{name}' whose code doesn't match its checksum"
+            )));
+        }
+    }
+
+    if let Some(name) = expected.keys().next() {
+        return Err(err(format!(
+            "has a checksum for module '{name}', which isn't in the manifest"
+        )));
+    }
+
+    Ok(entries)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn split_image_reads_modules_back() {
+        let mut image = b"app::math\0".to_vec();
+        image.extend_from_slice(&3u64.to_le_bytes());
+        image.extend_from_slice(b"x=1main\0");
+        image.extend_from_slice(&0u64.to_le_bytes());
+
+        let modules = split_image(&image).unwrap();
+        assert_eq!(
+            modules,
+            vec![
+                (String::from("app::math"), &b"x=1"[..]),
+                (String::from("main"), &b""[..])
+            ]
+        );
+    }
+
+    #[test]
+    fn split_image_rejects_huge_lengths() {
+        let mut image = b"m\0".to_vec();
+        image.extend_from_slice(&[0xff; 8]);
+
+        assert_eq!(
+            split_image(&image),
+            Err(String::from("has truncated code for module 'm'"))
+        );
+    }
+}
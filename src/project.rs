@@ -0,0 +1,483 @@
+use crate::bundle;
+use crate::cache::{self, Cache};
+use crate::compiler_internals::modules::{
+    load_project, ModuleError, ModuleFile, ModuleGraph, Package, SOURCE_EXTENSION,
+};
+use crate::package;
+use serde::Deserialize;
+use std::collections::{BTreeMap, HashMap};
+use std::fmt;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// File name of project manifests.
+pub const MANIFEST_NAME: &str = "Squid.toml";
+
+/// Project manifest, read from `Squid.toml`:
+///
+/// ```toml
+/// [package]
+/// name = "app"
+/// version = "0.1.0"
+/// entry = "src/main.sq"     # default
+/// source-dirs = ["src"]     # default
+///
+/// [dependencies]
+/// utils = { path = "../utils" }
+///
+/// [build]
+/// optimizations = 2         # default 0
+/// target-dir = "target"     # default
+///
+/// [bundle]
+/// enabled = true            # default false
+/// vm = "path/to/squid-vm"
+/// ```
+#[derive(Deserialize, Debug)]
+#[serde(deny_unknown_fields)]
+pub struct Manifest {
+    pub package: PackageInfo,
+    #[serde(default)]
+    pub dependencies: BTreeMap<String, Dependency>,
+    #[serde(default)]
+    pub build: BuildSettings,
+    #[serde(default)]
+    pub bundle: BundleSettings,
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(rename_all = "kebab-case", deny_unknown_fields)]
+pub struct PackageInfo {
+    pub name: String,
+    pub version: String,
+    /// Entry file, relative to the manifest
+    #[serde(default = "default_entry")]
+    pub entry: PathBuf,
+    /// Directories imports are resolved from, relative to the manifest
+    #[serde(default = "default_source_dirs")]
+    pub source_dirs: Vec<PathBuf>,
+}
+
+fn default_entry() -> PathBuf {
+    PathBuf::from("src/main.sq")
+}
+
+fn default_source_dirs() -> Vec<PathBuf> {
+    vec![PathBuf::from("src")]
+}
+
+/// Package imported under the name of its `[dependencies]` key, only local
+/// paths are supported.
+#[derive(Deserialize, Debug)]
+#[serde(deny_unknown_fields)]
+pub struct Dependency {
+    /// Directory holding its `Squid.toml`, relative to the manifest
+    pub path: PathBuf,
+}
+
+#[derive(Deserialize, Debug, Default)]
+#[serde(rename_all = "kebab-case", deny_unknown_fields)]
+pub struct BuildSettings {
+    /// Optimization level used when `-O` isn't given
+    #[serde(default)]
+    pub optimizations: u8,
+    /// Where build outputs go, relative to the manifest
+    pub target_dir: Option<PathBuf>,
+}
+
+#[derive(Deserialize, Debug, Default)]
+#[serde(deny_unknown_fields)]
+pub struct BundleSettings {
+    /// Whether `build` also makes a self-contained executable
+    #[serde(default)]
+    pub enabled: bool,
+    /// SquidVM executable to bundle with, relative to the manifest
+    pub vm: Option<PathBuf>,
+}
+
+/// Project loaded from its manifest, with its dependencies.
+pub struct Project {
+    /// Directory of the manifest.
+    pub root: PathBuf,
+    pub manifest: Manifest,
+    /// The project itself first, then its dependencies.
+    pub packages: Vec<Package>,
+    /// Manifest of each package, in the same order.
+    pub manifests: Vec<PathBuf>,
+    /// Entry file of each package, in the same order. It can be outside of
+    /// the source directories.
+    pub entries: Vec<PathBuf>,
+}
+
+/// Options given on the command line, they override the manifest.
+#[derive(Default)]
+pub struct BuildOptions {
+    pub optimizations: Option<u8>,
+    pub bundle: bool,
+    pub vm: Option<PathBuf>,
+    /// Compiles everything, without reading or writing the build cache.
+    pub no_cache: bool,
+}
+
+/// What a build produced.
+pub struct BuildOutput {
+    pub graph: ModuleGraph,
+    /// Number of modules compiled again, the others came from the cache.
+    pub recompiled: usize,
+    pub optimizations: u8,
+    /// Linked image of all modules.
+    pub image: PathBuf,
+    /// Self-contained executable, when bundling.
+    pub executable: Option<PathBuf>,
+}
+
+#[derive(Debug)]
+pub enum BuildError {
+    /// The manifest or one of its dependencies couldn't be read.
+    Project(String),
+    Module(ModuleError),
+    Cache(String),
+    /// The VM couldn't be bundled with the compiled modules.
+    Bundle(String),
+    /// The target directory or a file in it couldn't be written.
+    Output(String),
+}
+
+impl Project {
+    pub fn target_dir(&self) -> PathBuf {
+        self.root.join(
+            self.manifest
+                .build
+                .target_dir
+                .as_deref()
+                .unwrap_or(Path::new("target")),
+        )
+    }
+}
+
+impl fmt::Display for BuildError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            BuildError::Project(message)
+            | BuildError::Cache(message)
+            | BuildError::Bundle(message)
+            | BuildError::Output(message) => f.write_str(message),
+            BuildError::Module(err) => write!(f, "{err}"),
+        }
+    }
+}
+
+/// Looks for a `Squid.toml` in `start` and then in each of its parents.
+pub fn find_manifest(start: &Path) -> Option<PathBuf> {
+    start
+        .ancestors()
+        .map(|directory| directory.join(MANIFEST_NAME))
+        .find(|path| path.is_file())
+}
+
+fn read_manifest(path: &Path) -> Result<Manifest, BuildError> {
+    let text = fs::read_to_string(path)
+        .map_err(|err| BuildError::Project(format!("can't read '{}': {err}", path.display())))?;
+
+    toml::from_str(&text)
+        .map_err(|err| BuildError::Project(format!("invalid '{}': {err}", path.display())))
+}
+
+/// Reads the manifest at `path` and the manifests of its dependencies.
+pub fn load(path: &Path) -> Result<Project, BuildError> {
+    let root = path.parent().unwrap_or(Path::new("")).to_path_buf();
+    let manifest = read_manifest(path)?;
+
+    let mut packages = Vec::new();
+    let mut manifests = Vec::new();
+    let mut entries = Vec::new();
+    add_package(
+        &root,
+        path,
+        &manifest,
+        &mut packages,
+        &mut manifests,
+        &mut entries,
+        &mut HashMap::new(),
+    )?;
+
+    Ok(Project {
+        root,
+        manifest,
+        packages,
+        manifests,
+        entries,
+    })
+}
+
+/// Adds the package at `root` and, recursively, its dependencies, returning
+/// its index. Packages reached through several paths are only added once.
+fn add_package(
+    root: &Path,
+    manifest_path: &Path,
+    manifest: &Manifest,
+    packages: &mut Vec<Package>,
+    manifests: &mut Vec<PathBuf>,
+    entries: &mut Vec<PathBuf>,
+    seen: &mut HashMap<PathBuf, usize>,
+) -> Result<usize, BuildError> {
+    let key = root.canonicalize().unwrap_or_else(|_| root.to_path_buf());
+    if let Some(index) = seen.get(&key) {
+        return Ok(*index);
+    }
+
+    let index = packages.len();
+    seen.insert(key, index);
+    packages.push(Package {
+        sources: manifest
+            .package
+            .source_dirs
+            .iter()
+            .map(|directory| root.join(directory))
+            .collect(),
+        dependencies: HashMap::new(),
+        files: HashMap::new(),
+    });
+    manifests.push(manifest_path.to_path_buf());
+    entries.push(root.join(&manifest.package.entry));
+
+    for (name, dependency) in &manifest.dependencies {
+        let dependency_root = root.join(&dependency.path);
+        let dependency_path = dependency_root.join(MANIFEST_NAME);
+        let dependency_manifest = read_manifest(&dependency_path)?;
+
+        let dependency = add_package(
+            &dependency_root,
+            &dependency_path,
+            &dependency_manifest,
+            packages,
+            manifests,
+            entries,
+            seen,
+        )?;
+        packages[index]
+            .dependencies
+            .insert(name.clone(), dependency);
+    }
+
+    Ok(index)
+}
+
+/// Every source file in the source directories of the project itself, its
+/// dependencies left out.
+pub fn sources(project: &Project) -> Result<Vec<PathBuf>, BuildError> {
+    let mut files = Vec::new();
+
+    for source in &project.packages[0].sources {
+        files.extend(source_files(source)?);
+    }
+
+    Ok(files)
+}
+
+/// Every source file in the project source directories, named after their
+/// path in them, with the entry first.
+fn discover(project: &Project) -> Result<Vec<ModuleFile>, BuildError> {
+    let entry = project.root.join(&project.manifest.package.entry);
+    if !entry.is_file() {
+        return Err(BuildError::Project(format!(
+            "the entry file '{}' doesn't exist",
+            entry.display()
+        )));
+    }
+
+    let module = |source: &Path, path: &Path| ModuleFile {
+        name: path
+            .strip_prefix(source)
+            .unwrap_or(path)
+            .with_extension("")
+            .iter()
+            .map(|segment| segment.to_string_lossy())
+            .collect::<Vec<_>>()
+            .join("::"),
+        path: path.to_path_buf(),
+        package: Some(0),
+    };
+
+    let entry_source = project.packages[0]
+        .sources
+        .iter()
+        .find(|source| entry.starts_with(source));
+
+    let mut files = vec![match entry_source {
+        Some(source) => module(source, &entry),
+        None => module(entry.parent().unwrap_or(Path::new("")), &entry),
+    }];
+
+    for source in &project.packages[0].sources {
+        for path in source_files(source)? {
+            if path != entry {
+                files.push(module(source, &path));
+            }
+        }
+    }
+
+    Ok(files)
+}
+
+/// Every `.sq` file under `directory`, recursively, in a stable order.
+fn source_files(directory: &Path) -> Result<Vec<PathBuf>, BuildError> {
+    let read_err = |err: std::io::Error| {
+        BuildError::Project(format!("can't read '{}': {err}", directory.display()))
+    };
+
+    let mut files = Vec::new();
+
+    for entry in fs::read_dir(directory).map_err(read_err)? {
+        let path = entry.map_err(read_err)?.path();
+
+        if path.is_dir() {
+            files.extend(source_files(&path)?);
+        } else if path.extension().is_some_and(|e| e == SOURCE_EXTENSION) {
+            files.push(path);
+        }
+    }
+
+    files.sort();
+    Ok(files)
+}
+
+/// Resolves the imports of every module of the project, without the build
+/// cache and without writing anything.
+pub fn check(project: &Project) -> Result<ModuleGraph, BuildError> {
+    load_project(&project.packages, discover(project)?, |_| None).map_err(BuildError::Module)
+}
+
+/// VM executable given on the command line, or else the one of the manifest.
+pub fn vm(project: &Project, options: &BuildOptions) -> Option<PathBuf> {
+    match (&options.vm, &project.manifest.bundle.vm) {
+        (Some(vm), _) => Some(vm.clone()),
+        (None, Some(vm)) => Some(project.root.join(vm)),
+        (None, None) => None,
+    }
+}
+
+/// Compiles every module of the project into its target directory, and
+/// bundles it with the VM when enabled.
+///
+/// Unless disabled, the build cache skips tokenizing unchanged modules and
+/// reuses the artifacts of modules whose source and transitive imports
+/// haven't changed.
+pub fn build(project: &Project, options: &BuildOptions) -> Result<BuildOutput, BuildError> {
+    let manifest = &project.manifest;
+    let optimizations = options
+        .optimizations
+        .unwrap_or(manifest.build.optimizations);
+
+    let target = project.target_dir();
+    fs::create_dir_all(&target)
+        .map_err(|err| BuildError::Output(format!("can't create '{}': {err}", target.display())))?;
+
+    let mut cache = match options.no_cache {
+        true => None,
+        false => Some(Cache::open(&target.join(cache::CACHE_NAME)).map_err(BuildError::Cache)?),
+    };
+
+    let graph = load_project(&project.packages, discover(project)?, |source| {
+        cache.as_ref()?.summary(source)
+    })
+    .map_err(BuildError::Module)?;
+
+    let flags = format!("optimizations={optimizations}");
+    let mut keys: Vec<u64> = Vec::new();
+    let mut artifacts: Vec<Vec<u8>> = Vec::new();
+    let mut recompiled = 0;
+
+    // Modules come after their imports, so their keys are already known.
+    for module in &graph.modules {
+        let key = cache::artifact_key(
+            module,
+            &flags,
+            module
+                .dependencies
+                .iter()
+                .map(|dependency| keys[*dependency]),
+        );
+
+        let artifact = match cache
+            .as_ref()
+            .and_then(|cache| cache.artifact(&module.source.path, key))
+        {
+            Some(artifact) => artifact.to_vec(),
+            None => {
+                recompiled += 1;
+                package::module_image(module)
+            }
+        };
+
+        keys.push(key);
+        artifacts.push(artifact);
+    }
+
+    if let Some(cache) = &mut cache {
+        cache
+            .save(&graph, &keys, &artifacts)
+            .map_err(BuildError::Cache)?;
+    }
+
+    let payload = artifacts.concat();
+    let image = target.join(format!("{}.sqb", manifest.package.name));
+
+    // Outputs are written next to their destination and only moved there once
+    // everything succeeded, so a failed build leaves the previous ones intact.
+    let image_temp = image.with_extension("sqb.tmp");
+    fs::write(&image_temp, &payload).map_err(|err| {
+        BuildError::Output(format!("can't write '{}': {err}", image_temp.display()))
+    })?;
+
+    let executable = if options.bundle || manifest.bundle.enabled {
+        let vm = match vm(project, options) {
+            Some(vm) => vm,
+            None => {
+                let _ = fs::remove_file(&image_temp);
+                return Err(BuildError::Bundle(String::from(
+                    "bundling needs the VM executable, set 'vm' in [bundle] or pass --vm",
+                )));
+            }
+        };
+
+        let executable = target.join(format!(
+            "{}{}",
+            manifest.package.name,
+            std::env::consts::EXE_SUFFIX
+        ));
+        let executable_temp = target.join(format!("{}.tmp", manifest.package.name));
+
+        if let Err(err) = bundle::bundle(&vm, &payload, &executable_temp) {
+            let _ = fs::remove_file(&image_temp);
+            let _ = fs::remove_file(&executable_temp);
+            return Err(BuildError::Bundle(err));
+        }
+
+        Some((executable_temp, executable))
+    } else {
+        None
+    };
+
+    let rename = |from: &Path, to: &Path| {
+        fs::rename(from, to)
+            .map_err(|err| BuildError::Output(format!("can't write '{}': {err}", to.display())))
+    };
+
+    rename(&image_temp, &image)?;
+    let executable = match executable {
+        Some((temp, executable)) => {
+            rename(&temp, &executable)?;
+            Some(executable)
+        }
+        None => None,
+    };
+
+    Ok(BuildOutput {
+        graph,
+        recompiled,
+        optimizations,
+        image,
+        executable,
+    })
+}
@@ -0,0 +1,227 @@
+use crate::bundle;
+use crate::codes::ErrorCode;
+use crate::compiler_internals::modules::{
+    load_project, ModuleError, ModuleFile, ModuleGraph, Package,
+};
+use std::collections::HashMap;
+use std::fmt;
+use std::path::{Path, PathBuf};
+
+/// Options of a compilation.
+///
+/// More options will be added, so start from [`Options::default`] and set the
+/// fields that matter:
+///
+/// ```
+/// let mut options = squidc::Options::default();
+/// options.path = "src/app.sq".into();
+/// ```
+#[derive(Clone, Debug)]
+#[non_exhaustive]
+pub struct Options {
+    /// Number of optimization steps. There's no optimizer yet, so it doesn't
+    /// change the bytecode.
+    pub optimizations: u8,
+    /// Path the source given to [`Session::compile`] is compiled as, its
+    /// imports are resolved from its directory. Defaults to `main.sq`.
+    pub path: PathBuf,
+}
+
+impl Default for Options {
+    fn default() -> Self {
+        Self {
+            optimizations: 0,
+            path: PathBuf::from("main.sq"),
+        }
+    }
+}
+
+/// Result of a successful compilation.
+pub struct Artifact {
+    /// Names of the compiled modules in link order, the entry last.
+    pub modules: Vec<String>,
+    /// The [`package::module_image`] of each module in link order, what
+    /// `squidc build` writes to `.sqb`.
+    ///
+    /// There's no code generator yet, so despite the name these images hold
+    /// the source text of the modules, not compiled bytecode. The format will
+    /// change once there is one.
+    ///
+    /// [`package::module_image`]: crate::package::module_image
+    pub bytecode: Vec<u8>,
+    /// Warnings found in the modules, they didn't stop the compilation.
+    pub warnings: Vec<Diagnostic>,
+    graph: ModuleGraph,
+}
+
+impl Artifact {
+    /// The loaded modules, for tools that need more than the bytecode.
+    ///
+    /// **Unstable:** its types come from
+    /// [`compiler_internals`](crate::compiler_internals) and change with the
+    /// compiler, unlike the rest of the [`Session`] API.
+    pub fn graph(&self) -> &ModuleGraph {
+        &self.graph
+    }
+
+    /// Takes the loaded modules out of the artifact, see [`Artifact::graph`].
+    ///
+    /// **Unstable**, like [`Artifact::graph`].
+    pub fn into_graph(self) -> ModuleGraph {
+        self.graph
+    }
+}
+
+/// Error or warning found in a source file.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Diagnostic {
+    /// Explained by `squidc --explain`.
+    pub code: ErrorCode,
+    pub message: String,
+    pub file: PathBuf,
+    /// Byte offset in `file`.
+    pub position: usize,
+}
+
+/// Errors that made a compilation fail.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Diagnostics(pub Vec<Diagnostic>);
+
+impl fmt::Debug for Artifact {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Artifact")
+            .field("modules", &self.modules)
+            .field("bytecode", &format_args!("[{} bytes]", self.bytecode.len()))
+            .finish_non_exhaustive()
+    }
+}
+
+impl fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{}: [{}] {} at byte {}",
+            self.file.display(),
+            self.code,
+            self.message,
+            self.position
+        )
+    }
+}
+
+impl fmt::Display for Diagnostics {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (index, diagnostic) in self.0.iter().enumerate() {
+            if index > 0 {
+                writeln!(f)?;
+            }
+            write!(f, "{diagnostic}")?;
+        }
+
+        Ok(())
+    }
+}
+
+impl std::error::Error for Diagnostics {}
+
+impl From<ModuleError> for Diagnostic {
+    fn from(err: ModuleError) -> Self {
+        Diagnostic {
+            code: err.code,
+            message: err.message,
+            file: err.file,
+            position: err.position,
+        }
+    }
+}
+
+impl From<ModuleError> for Diagnostics {
+    fn from(err: ModuleError) -> Self {
+        Diagnostics(vec![err.into()])
+    }
+}
+
+/// Compiler instance that can be called many times in-process.
+///
+/// Sources added with [`Session::add_source`] are kept in memory and imported
+/// as if they were files next to the entry, they take precedence over the
+/// files on disk.
+pub struct Session {
+    options: Options,
+    /// Added sources, by their path relative to the entry directory.
+    files: HashMap<PathBuf, String>,
+}
+
+impl Session {
+    pub fn new(options: Options) -> Self {
+        Self {
+            options,
+            files: HashMap::new(),
+        }
+    }
+
+    pub fn options(&self) -> &Options {
+        &self.options
+    }
+
+    /// Adds a source file that imports can resolve to, at `path` relative to
+    /// the directory of the entry, like `app/math.sq` for `import app::math;`.
+    pub fn add_source(&mut self, path: impl Into<PathBuf>, code: impl Into<String>) {
+        self.files.insert(path.into(), code.into());
+    }
+
+    /// Compiles `source` as the entry file at [`Options::path`].
+    pub fn compile(&self, source: &str) -> Result<Artifact, Diagnostics> {
+        self.compile_entry(&self.options.path, Some(source))
+    }
+
+    /// Compiles the entry file at `path`, read from disk unless it was added
+    /// with [`Session::add_source`].
+    pub fn compile_file(&self, path: &Path) -> Result<Artifact, Diagnostics> {
+        self.compile_entry(path, None)
+    }
+
+    fn compile_entry(&self, entry: &Path, source: Option<&str>) -> Result<Artifact, Diagnostics> {
+        let root = entry.parent().unwrap_or(Path::new("")).to_path_buf();
+
+        let mut files: HashMap<PathBuf, String> = self
+            .files
+            .iter()
+            .map(|(path, code)| (root.join(path), code.clone()))
+            .collect();
+
+        if let Some(source) = source {
+            files.insert(entry.to_path_buf(), source.to_string());
+        }
+
+        let package = Package {
+            sources: vec![root],
+            dependencies: HashMap::new(),
+            files,
+        };
+
+        let entry = ModuleFile {
+            name: String::from("main"),
+            path: entry.to_path_buf(),
+            package: Some(0),
+        };
+
+        let graph = load_project(&[package], vec![entry], |_| None)?;
+
+        Ok(Artifact {
+            modules: graph
+                .modules
+                .iter()
+                .map(|module| module.name.clone())
+                .collect(),
+            bytecode: bundle::image(&graph),
+            warnings: graph.warnings().map(Diagnostic::from).collect(),
+            graph,
+        })
+    }
+}
+
+/// Compiles `source` with `options` in a new [`Session`].
+pub fn compile(source: &str, options: &Options) -> Result<Artifact, Diagnostics> {
+    Session::new(options.clone()).compile(source)
+}
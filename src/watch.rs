@@ -0,0 +1,119 @@
+use crate::project::Project;
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::thread;
+use std::time::{Duration, SystemTime};
+
+/// How often watched files are checked.
+const POLL_INTERVAL: Duration = Duration::from_millis(200);
+
+/// How long files must stay unchanged before a rebuild, so that a burst of
+/// saves only triggers one.
+const DEBOUNCE: Duration = Duration::from_millis(300);
+
+/// Modification time and size of each watched file.
+type Snapshot = BTreeMap<PathBuf, (Option<SystemTime>, u64)>;
+
+/// Files and directories a build of `project` reads: the manifests, the entry
+/// files and the source directories of every package.
+pub fn watched_paths(project: &Project) -> Vec<PathBuf> {
+    project
+        .manifests
+        .iter()
+        .chain(&project.entries)
+        .chain(project.packages.iter().flat_map(|package| &package.sources))
+        .cloned()
+        .collect()
+}
+
+/// Blocks until one of `paths`, or a file under them, is created, removed or
+/// modified, and then until they've stayed unchanged for a moment.
+pub fn wait_for_changes(paths: &[PathBuf]) {
+    let before = snapshot(paths);
+
+    let mut current = loop {
+        thread::sleep(POLL_INTERVAL);
+
+        let current = snapshot(paths);
+        if current != before {
+            break current;
+        }
+    };
+
+    loop {
+        thread::sleep(DEBOUNCE);
+
+        let next = snapshot(paths);
+        if next == current {
+            return;
+        }
+        current = next;
+    }
+}
+
+fn snapshot(paths: &[PathBuf]) -> Snapshot {
+    let mut snapshot = Snapshot::new();
+
+    for path in paths {
+        add_to_snapshot(path, &mut snapshot);
+    }
+
+    snapshot
+}
+
+/// Adds `path` to `snapshot`, with everything under it if it's a directory.
+/// Missing and unreadable files are left out, so they count as changed once
+/// they show up.
+fn add_to_snapshot(path: &Path, snapshot: &mut Snapshot) {
+    let Ok(metadata) = fs::metadata(path) else {
+        return;
+    };
+
+    if metadata.is_dir() {
+        if let Ok(entries) = fs::read_dir(path) {
+            for entry in entries.flatten() {
+                add_to_snapshot(&entry.path(), snapshot);
+            }
+        }
+    } else {
+        snapshot.insert(
+            path.to_path_buf(),
+            (metadata.modified().ok(), metadata.len()),
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::project;
+
+    #[test]
+    fn entries_outside_the_sources_are_watched() {
+        let root = std::env::temp_dir().join(format!("squidc-watch-{}", std::process::id()));
+        let utils = root.join("utils");
+        fs::create_dir_all(&utils).unwrap();
+
+        fs::write(
+            root.join(project::MANIFEST_NAME),
+            "[package]\nname = \"app\"\nversion = \"0.1.0\"\nentry = \"main.sq\"\n\n\
+             [dependencies]\nutils = { path = \"utils\" }\n",
+        )
+        .unwrap();
+        fs::write(
+            utils.join(project::MANIFEST_NAME),
+            "[package]\nname = \"utils\"\nversion = \"0.1.0\"\nentry = \"lib.sq\"\n",
+        )
+        .unwrap();
+
+        let project = project::load(&root.join(project::MANIFEST_NAME)).unwrap();
+        let paths = watched_paths(&project);
+        fs::remove_dir_all(&root).unwrap();
+
+        assert!(paths.contains(&root.join("main.sq")));
+        assert!(paths.contains(&utils.join("lib.sq")));
+        assert!(paths.contains(&root.join("src")));
+        assert!(paths.contains(&utils.join(project::MANIFEST_NAME)));
+    }
+}